@@ -1,162 +1,381 @@
-use crate::adapters::btc::BTCAdapter;
-use crate::adapters::cosmwasm_smart_query::CosmWasmSmartQueryAdapter;
-use crate::adapters::eth::ETHAdapter;
-use crate::adapters::morpho::MorphoAdapter;
-use crate::adapters::morphov2::MorphoV2Adapter;
-use crate::adapters::{
-    base::MetricsAdapter, compound::CompoundAdapter, cosmos_bank::CosmosBankAdapter,
-    erc20::Erc20Adapter,
+use crate::adapters::base::{
+    AdapterConfig, EndpointConfig, ExportUnit, Family, Measurement, MetricsAdapter, Observation,
+    check_url,
 };
-use crate::config::MetricConfig;
+use crate::adapters::btc::BtcConfig;
+use crate::adapters::chainlink::ChainlinkConfig;
+use crate::adapters::compound::CompoundConfig;
+use crate::adapters::cosmos_bank::CosmosBankConfig;
+use crate::adapters::cosmwasm_smart_query::CosmWasmSmartQueryConfig;
+use crate::adapters::erc20::Erc20Config;
+use crate::adapters::eth::EthConfig;
+use crate::adapters::evm_call::EvmCallConfig;
+use crate::adapters::morpho::MorphoConfig;
+use crate::adapters::morphov2::MorphoV2Config;
+use crate::adapters::pyth::PythConfig;
+use crate::address_book;
+use crate::chains::{Chains, Endpoint, Network, check_chain_id, resolve};
+use crate::config::{Config, ConfigErrors, MetricConfig, Rpc, RpcMode};
+use crate::pricing;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
-pub async fn create_adapter(
-    name: String,
-    metrics: Arc<Mutex<HashMap<String, super::adapters::base::Value>>>,
-    config: &MetricConfig,
-) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn Error>> {
-    println!("Creating adapter: {}", config.adapter);
-    match config.adapter.as_str() {
-        "btc" => {
-            let addresses = config.config["addresses"]
-                .as_array()
-                .ok_or("Missing addresses")?
-                .iter()
-                .map(|v| v.as_str().unwrap())
-                .collect::<Vec<_>>();
-
-            Ok(Box::new(BTCAdapter::new(&name, metrics, addresses).await?))
+/// The adapter types a config may use, by the name given in `adapter = "..."`.
+/// `AdapterRegistry::default()` holds the built-in adapters; a crate using
+/// this one as a library can `register` its own before starting the runtime.
+#[derive(Clone)]
+pub struct AdapterRegistry {
+    parsers: HashMap<String, Parser>,
+}
+
+/// Parses the `[metrics.<name>]` entry at the given path.
+type Parser = fn(&str, &MetricConfig) -> Result<TypedConfig, Vec<String>>;
+
+impl AdapterRegistry {
+    /// A registry without any adapters.
+    pub fn empty() -> Self {
+        Self {
+            parsers: HashMap::new(),
         }
-        "compound" | "erc20" | "eth" | "morpho" | "morphov2" => {
-            let addresses = config.config["addresses"]
-                .as_array()
-                .ok_or("Missing addresses")?
-                .iter()
-                .map(|v| v.as_str().unwrap())
-                .collect::<Vec<_>>();
-
-            let rpc = config.config["rpc"].as_str().ok_or("Missing rpc")?;
-
-            let decimals: u8 = config.config["decimals"].as_u64().unwrap() as u8 - 2;
-
-            match config.adapter.as_str() {
-                "compound" => {
-                    let contract = config.config["contract"]
-                        .as_str()
-                        .ok_or("Missing contract")?;
-                    Ok(Box::new(
-                        CompoundAdapter::new(&name, metrics, addresses, contract, rpc, decimals)
-                            .await?,
-                    ))
-                }
-                "erc20" => {
-                    let contract = config.config["contract"]
-                        .as_str()
-                        .ok_or("Missing contract")?;
-                    Ok(Box::new(
-                        Erc20Adapter::new(&name, metrics, addresses, contract, rpc, decimals)
-                            .await?,
-                    ))
-                }
-                "morpho" => {
-                    let contract = config.config["contract"]
-                        .as_str()
-                        .ok_or("Missing contract")?;
-                    Ok(Box::new(
-                        MorphoAdapter::new(&name, metrics, addresses, contract, rpc, decimals)
-                            .await?,
-                    ))
-                }
-                "eth" => Ok(Box::new(
-                    ETHAdapter::new(&name, metrics, addresses, rpc, decimals).await?,
+    }
+
+    /// Makes the adapter configured by `C` available as `adapter = "<name>"`,
+    /// replacing any adapter registered under the same name.
+    pub fn register<C: AdapterConfig>(&mut self, name: &str) -> &mut Self {
+        self.parsers.insert(name.to_string(), |path, metric| {
+            let (config, warnings) = parse_table::<C>(path, metric)?;
+            Ok(TypedConfig {
+                adapter_type: metric.adapter.clone(),
+                default_chain: C::DEFAULT_CHAIN,
+                network: C::NETWORK,
+                legacy_unit: C::LEGACY_UNIT,
+                config: Arc::new(config),
+                warnings,
+            })
+        });
+        self
+    }
+
+    /// Parses and checks the global settings and every `[chains.*]`,
+    /// `[addresses.*]`, `[prices.*]` and `[metrics.*]` entry, enabled or not,
+    /// and reports all problems together so a config is either fully usable
+    /// or rejected before any adapter starts.
+    pub fn validate(&self, config: &Config) -> Result<HashMap<String, TypedConfig>, ConfigErrors> {
+        let mut problems = Vec::new();
+        // Zero would panic the schedulers' timers, or never admit an adapter.
+        if config.concurrency == 0 {
+            problems.push("concurrency: must be at least 1".to_string());
+        }
+        if config.interval == 0 {
+            problems.push("interval: must be at least 1".to_string());
+        }
+        check_timeout(&mut problems, "timeout", config.timeout, config.interval);
+        let mut chains = config.chains.iter().collect::<Vec<_>>();
+        chains.sort_by_key(|(name, _)| *name);
+        for (name, chain) in chains {
+            let urls = chain.rpc.urls();
+            if urls.is_empty() {
+                problems.push(format!("chains.{}.rpc: no URLs", name));
+            }
+            for (i, url) in urls.iter().enumerate() {
+                let field = match chain.rpc {
+                    Rpc::One(_) => format!("chains.{}.rpc", name),
+                    Rpc::Many(_) => format!("chains.{}.rpc[{}]", name, i),
+                };
+                check_url(&mut problems, &field, url);
+            }
+            match chain.quorum {
+                Some(_) if chain.rpc_mode != RpcMode::Quorum => problems.push(format!(
+                    "chains.{}.quorum: only applies with rpc_mode = \"quorum\"",
+                    name
+                )),
+                Some(quorum) if quorum == 0 || quorum > urls.len() => problems.push(format!(
+                    "chains.{}.quorum: must be between 1 and the {} rpc URLs",
+                    name,
+                    urls.len()
                 )),
-                "morphov2" => {
-                    let contract = config.config["contract"]
-                        .as_str()
-                        .ok_or("Missing contract")?;
-                    Ok(Box::new(
-                        MorphoV2Adapter::new(&name, metrics, addresses, contract, rpc).await?,
-                    ))
+                _ => {}
+            }
+            if chain.rate_limit == Some(0) {
+                problems.push(format!("chains.{}.rate_limit: must be at least 1", name));
+            }
+        }
+
+        problems.extend(address_book::validate(config));
+        problems.extend(pricing::validate(config));
+
+        let mut names = config.metrics.keys().collect::<Vec<_>>();
+        names.sort();
+
+        let mut typed = HashMap::new();
+        for name in names {
+            let path = format!("metrics.{}", name);
+            let metric = &config.metrics[name];
+            if metric.interval == Some(0) {
+                problems.push(format!("{}.interval: must be at least 1", path));
+            }
+            // Values would expire between successful updates.
+            let interval = metric.interval.unwrap_or(config.interval);
+            let field = format!("{}.timeout", path);
+            check_timeout(&mut problems, &field, metric.timeout, interval);
+            if metric
+                .stale
+                .max_age
+                .is_some_and(|max_age| max_age < interval)
+            {
+                problems.push(format!(
+                    "{}.stale.max_age: must be at least the interval of {}s",
+                    path, interval
+                ));
+            }
+            let parsed = match self.parsers.get(&metric.adapter) {
+                Some(parse) => parse(&path, metric),
+                None => Err(vec![format!(
+                    "{}.adapter: unknown adapter {:?}",
+                    path, metric.adapter
+                )]),
+            };
+            match parsed {
+                Ok(adapter) => {
+                    if let Some(endpoint) = adapter.endpoint()
+                        && let Err(problem) = resolve(&config.chains, endpoint)
+                            .and_then(|_| check_chain_id(&config.chains, endpoint, adapter.network))
+                    {
+                        problems.push(format!("metrics.{}.config.{}", name, problem));
+                        continue;
+                    }
+                    typed.insert(name.clone(), adapter);
                 }
-                _ => unreachable!(),
+                Err(mut errors) => problems.append(&mut errors),
             }
         }
-        "cosmos_bank" => {
-            let addresses = config.config["addresses"]
-                .as_array()
-                .ok_or("Missing addresses")?
-                .iter()
-                .map(|v| v.as_str().unwrap())
-                .collect::<Vec<_>>();
-
-            let denoms = config.config["denoms"]
-                .as_array()
-                .ok_or("Missing denoms")?
-                .iter()
-                .map(|v| v.as_str().unwrap())
-                .collect::<Vec<_>>();
-
-            let rpc_endpoint = config.config["rpc"].as_str().ok_or("Missing rpc")?;
-
-            Ok(Box::new(
-                CosmosBankAdapter::new(&name, metrics, addresses, rpc_endpoint, denoms).await?,
-            ))
+
+        if problems.is_empty() {
+            Ok(typed)
+        } else {
+            Err(ConfigErrors(problems))
+        }
+    }
+}
+
+impl Default for AdapterRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register::<BtcConfig>("btc")
+            .register::<EthConfig>("eth")
+            .register::<Erc20Config>("erc20")
+            .register::<CompoundConfig>("compound")
+            .register::<MorphoConfig>("morpho")
+            .register::<MorphoV2Config>("morphov2")
+            .register::<CosmosBankConfig>("cosmos_bank")
+            .register::<CosmWasmSmartQueryConfig>("cosmwasm_smart_query")
+            .register::<EvmCallConfig>("evm_call")
+            .register::<PythConfig>("pyth")
+            .register::<ChainlinkConfig>("chainlink");
+        registry
+    }
+}
+
+/// The `config` table of an adapter, parsed into its registered type.
+#[derive(Debug, Clone)]
+pub struct TypedConfig {
+    adapter_type: String,
+    default_chain: &'static str,
+    network: Network,
+    legacy_unit: ExportUnit,
+    config: Arc<dyn AnyConfig>,
+    warnings: Vec<String>,
+}
+
+impl TypedConfig {
+    pub fn adapter_type(&self) -> &str {
+        &self.adapter_type
+    }
+
+    pub fn legacy_unit(&self) -> ExportUnit {
+        self.legacy_unit
+    }
+
+    /// Fields of the config table that were ignored, for the caller to log
+    /// once, as `field: message`.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn endpoint(&self) -> Option<Endpoint<'_>> {
+        self.config.endpoint()
+    }
+
+    /// Connects the way the adapter would, for `check-config --connect`.
+    pub async fn check(&self, chains: &Chains) -> Result<String, Box<dyn Error>> {
+        if let Some(endpoint) = self.endpoint() {
+            chains.verify_chain_id(endpoint).await?;
+        }
+        self.config.check(chains).await
+    }
+}
+
+/// `AdapterConfig` without its `Sized` supertraits, so parsed configs of
+/// every registered type can be held together.
+#[async_trait::async_trait(?Send)]
+trait AnyConfig: fmt::Debug + Send + Sync {
+    fn endpoint(&self) -> Option<Endpoint<'_>>;
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn Error>>;
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn Error>>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl<C: AdapterConfig> AnyConfig for C {
+    fn endpoint(&self) -> Option<Endpoint<'_>> {
+        self.endpoint_config().map(EndpointConfig::as_endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn Error>> {
+        AdapterConfig::build(self, name, chains).await
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn Error>> {
+        AdapterConfig::check(self, chains).await
+    }
+}
+
+/// Logs the warnings of the adapters for which `logged` holds, by name.
+pub fn log_warnings(adapters: &HashMap<String, TypedConfig>, logged: impl Fn(&str) -> bool) {
+    let mut names = adapters
+        .keys()
+        .filter(|name| logged(name))
+        .collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        for warning in adapters[name].warnings() {
+            eprintln!("Warning: {}", warning);
         }
-        "cosmwasm_smart_query" => {
-            let contract = config.config["contract"]
-                .as_str()
-                .ok_or("Missing contract")?;
-
-            let rpc_endpoint = config.config["rpc"].as_str().ok_or("Missing rpc")?;
-            let objects = config.config["objects"]
-                .as_array()
-                .ok_or("Missing objects")?
-                .iter()
-                .map(|v| {
-                    let query = v["query"].as_str().unwrap();
-                    let keys = v["keys"]
-                        .as_array()
-                        .unwrap()
-                        .iter()
-                        .map(|v| {
-                            let value_type = v["type"].as_str().unwrap_or("Type not found");
-                            let value_type = match value_type {
-                                "int" => super::adapters::base::ValueType::Int,
-                                "float" => super::adapters::base::ValueType::Float,
-                                _ => panic!("Unknown value type: {}", value_type),
-                            };
-                            (
-                                v["key"].as_str().unwrap(),
-                                v["path"].as_str().unwrap(),
-                                value_type,
-                            )
-                        })
-                        .collect::<Vec<_>>();
-                    (query, keys)
-                })
-                .collect::<Vec<_>>();
-
-            let queries = objects
-                .iter()
-                .fold(HashMap::new(), |mut acc, (query, keys)| {
-                    acc.insert(
-                        query.to_string(),
-                        keys.clone()
-                            .iter()
-                            .map(|(a, b, c)| (a.to_string(), b.to_string(), c.clone()))
-                            .collect::<Vec<_>>(),
-                    );
-                    acc
-                });
-
-            Ok(Box::new(
-                CosmWasmSmartQueryAdapter::new(&name, contract, metrics, queries, rpc_endpoint)
-                    .await?,
-            ))
+    }
+}
+
+/// The `chain` label of an adapter's series: `chain` from its config, or the
+/// network the adapter type talks to.
+pub fn chain_label<'a>(metric: &'a MetricConfig, typed: &TypedConfig) -> &'a str {
+    metric.config["chain"]
+        .as_str()
+        .unwrap_or(typed.default_chain)
+}
+
+/// A timeout longer than the interval would let a hung update run into the
+/// next one.
+fn check_timeout(problems: &mut Vec<String>, field: &str, timeout: Option<u64>, interval: u64) {
+    if timeout.is_some_and(|timeout| timeout == 0 || timeout > interval) {
+        problems.push(format!(
+            "{}: must be between 1 and the interval of {}s",
+            field, interval
+        ));
+    }
+}
+
+/// Parses an adapter's config table, returning it with a warning for every
+/// field it ignored.
+fn parse_table<T: AdapterConfig>(
+    path: &str,
+    metric: &MetricConfig,
+) -> Result<(T, Vec<String>), Vec<String>> {
+    let path = format!("{}.config", path);
+    let mut ignored = Vec::new();
+    let mut record = |field: serde_ignored::Path| ignored.push(field.to_string());
+    let deserializer = serde_ignored::Deserializer::new(metric.config.clone(), &mut record);
+    let result: Result<T, _> = serde_path_to_error::deserialize(deserializer);
+    if let Ok(config) = &result
+        && let Some(endpoint) = config.endpoint_config()
+    {
+        ignored.extend(endpoint.unknown_fields().map(str::to_string));
+    }
+    let warnings = ignored
+        .into_iter()
+        .map(|field| format!("{}.{}: unknown field, ignored", path, field))
+        .collect();
+
+    let config = result.map_err(|e| {
+        // Errors in the flattened endpoint fields come without a path, which
+        // parsing those fields alone finds.
+        let endpoint = serde_path_to_error::deserialize::<_, EndpointConfig>(&metric.config);
+        let e = match endpoint {
+            Err(endpoint) if e.path().iter().next().is_none() => endpoint,
+            _ => e,
+        };
+        match e.path().to_string().as_str() {
+            "." => vec![format!("{}: {}", path, e.inner())],
+            field => vec![format!("{}.{}: {}", path, field, e.inner())],
         }
-        _ => Err(format!("Unknown adapter: {}", config.adapter).into()),
+    })?;
+    let mut problems = config.validate();
+    if let Some(endpoint) = config.endpoint_config() {
+        problems.extend(endpoint.validate(T::NETWORK));
+    }
+    if problems.is_empty() {
+        Ok((config, warnings))
+    } else {
+        Err(problems
+            .into_iter()
+            .map(|problem| format!("{}.{}", path, problem))
+            .collect())
+    }
+}
+
+/// Builds an adapter on the shared client of its chain, after checking that
+/// the client serves the expected chain id if one is set. Only its name and
+/// type are logged, since the config may hold resolved secrets.
+pub async fn create_adapter(
+    name: String,
+    config: &TypedConfig,
+    chains: &Chains,
+) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn Error>> {
+    println!("Creating adapter {} ({})", name, config.adapter_type());
+    let chain_id = match config.endpoint() {
+        Some(endpoint) => chains.verify_chain_id(endpoint).await?,
+        None => None,
+    };
+    let adapter = config.config.build(&name, chains).await?;
+    Ok(match chain_id {
+        Some(chain_id) => Box::new(OnChain {
+            adapter,
+            chain_id: chain_id.to_string(),
+        }),
+        None => adapter,
+    })
+}
+
+/// An adapter whose rpc serves `chain_id`, exported as `pex_chain_info`.
+struct OnChain {
+    adapter: Box<dyn MetricsAdapter + Send + Sync>,
+    chain_id: String,
+}
+
+#[async_trait::async_trait]
+impl MetricsAdapter for OnChain {
+    fn get_name(&self) -> &str {
+        self.adapter.get_name()
+    }
+
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn Error>> {
+        let mut observations = self.adapter.collect().await?;
+        observations.push(Observation::new(
+            Family::ChainInfo,
+            &[&self.chain_id],
+            Measurement::Int(1),
+            "chain_info".to_string(),
+        ));
+        Ok(observations)
     }
 }
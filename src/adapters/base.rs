@@ -1,53 +1,499 @@
-use std::{collections::HashMap, sync::Arc};
+use std::collections::BTreeMap;
+use std::fmt;
 
-use prometheus::{Gauge, IntGauge, Registry, core::GenericGauge};
-use tokio::sync::Mutex;
+use crate::chains::{Chains, Endpoint, Network};
+use crate::config::ChainId;
+use ethers::types::U256;
+use prometheus::GaugeVec;
+use prometheus::core::{Collector, GenericGauge};
+use serde::Deserialize;
+use serde::de::IgnoredAny;
 
 #[async_trait::async_trait]
 pub trait MetricsAdapter {
     fn get_name(&self) -> &str;
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+    /// Reads the current values. Adapters only fetch; the runtime turns the
+    /// observations into series and owns their registration and naming.
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>>;
+}
 
-    fn get_key(&self, param_name: &str) -> String {
-        format!("{}_{}", self.get_name(), param_name)
-    }
+/// One value read by an adapter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub family: Family,
+    /// Label values in the order of `Family::labels`.
+    pub labels: Vec<String>,
+    pub value: Measurement,
+    /// Identifies the series within its adapter. Legacy flat names are
+    /// `<adapter>_<key>`.
+    pub key: String,
+}
 
-    async fn register_param(
-        &self,
-        registry: Arc<Registry>,
-        metrics: Arc<Mutex<HashMap<String, Value>>>,
-        param_name: &str,
-        param_type: ValueType,
-    ) -> () {
-        let key = self.get_key(param_name);
-        let mut metrics = metrics.lock().await;
-        match param_type {
-            ValueType::Int => {
-                let gauge = IntGauge::new(key.clone(), format!("Value of {}", key)).unwrap();
-                registry.register(Box::new(gauge.clone())).unwrap();
-                metrics.insert(key.clone(), Value::Int(gauge));
-            }
-            ValueType::Float => {
-                let gauge = Gauge::new(key.clone(), format!("Value of {}", key)).unwrap();
-                registry.register(Box::new(gauge.clone())).unwrap();
-                metrics.insert(key.clone(), Value::Float(gauge));
-            }
+impl Observation {
+    pub fn new(family: Family, labels: &[&str], value: Measurement, key: String) -> Self {
+        Self {
+            family,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            value,
+            key,
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        self.family.name()
+    }
+
+    pub fn help(&self) -> &'static str {
+        self.family.help()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measurement {
+    Int(i64),
+    Float(f64),
+    /// A token amount, exported in the adapter's configured unit.
+    Amount(Amount),
+}
+
+#[derive(Debug, Clone)]
 pub enum Value {
     Int(GenericGauge<prometheus::core::AtomicI64>),
     Float(GenericGauge<prometheus::core::AtomicF64>),
+    Vec(GaugeVec),
 }
 
-#[derive(Debug, Clone)]
+impl Value {
+    /// Sets every series to NaN. Integer gauges cannot hold NaN, so this
+    /// returns `false` for them and leaves them untouched.
+    pub fn set_nan(&self) -> bool {
+        match self {
+            Value::Int(_) => false,
+            Value::Float(gauge) => {
+                gauge.set(f64::NAN);
+                true
+            }
+            Value::Vec(vec) => {
+                let variable_labels = vec.desc()[0].variable_labels.clone();
+                for family in vec.collect() {
+                    for metric in family.get_metric() {
+                        let values = variable_labels
+                            .iter()
+                            .filter_map(|name| {
+                                metric
+                                    .get_label()
+                                    .iter()
+                                    .find(|pair| pair.get_name() == name)
+                                    .map(|pair| pair.get_value())
+                            })
+                            .collect::<Vec<_>>();
+                        vec.with_label_values(&values).set(f64::NAN);
+                    }
+                }
+                true
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ValueType {
     Int,
     Float,
 }
+
+/// An adapter's `[metrics.<name>.config]` table. Registering the type with an
+/// `AdapterRegistry` makes its adapter available under a name.
+#[async_trait::async_trait(?Send)]
+pub trait AdapterConfig: serde::de::DeserializeOwned + fmt::Debug + Send + Sync + 'static {
+    /// The `chain` label of series from adapters that do not set `chain`.
+    const DEFAULT_CHAIN: &'static str = "ethereum";
+    /// The kind of chain the adapter's endpoint is on.
+    const NETWORK: Network = Network::Evm;
+    /// The unit of amounts under legacy metric names, the one they had before
+    /// amounts were scaled exactly.
+    const LEGACY_UNIT: ExportUnit = ExportUnit::Raw;
+
+    /// Problems serde cannot catch, each as `field: message`.
+    fn validate(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Where the adapter connects, or `None` for adapters that use a fixed
+    /// public API.
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        None
+    }
+
+    /// Builds the adapter, on the shared client of its chain if it has one.
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>>;
+
+    /// Connects the way the adapter would and returns what was found, e.g. the
+    /// chain id, for `check-config --connect`.
+    async fn check(&self, _chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        Ok("no connectivity check".to_string())
+    }
+}
+
+/// The `chain`, `rpc` and `chain_id` of an adapter that connects to a chain,
+/// flattened into its config with `#[serde(flatten)]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EndpointConfig {
+    pub chain: Option<String>,
+    pub rpc: Option<String>,       // overrides the rpc of `chain`
+    pub chain_id: Option<ChainId>, // checked against the rpc at startup, overrides the chain's
+    // Flattening hides the fields nobody took from `serde_ignored`, so they
+    // end up here to be reported as unknown.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl EndpointConfig {
+    pub fn as_endpoint(&self) -> Endpoint<'_> {
+        Endpoint {
+            chain: self.chain.as_deref(),
+            rpc: self.rpc.as_deref(),
+            chain_id: self.chain_id.as_ref(),
+        }
+    }
+
+    /// Problems with `rpc` as a URL for `network`, each as `field: message`.
+    pub fn validate(&self, network: Network) -> Vec<String> {
+        let mut problems = Vec::new();
+        match (&self.rpc, network) {
+            (Some(rpc), Network::Evm) => check_url(&mut problems, "rpc", rpc),
+            (Some(rpc), Network::Cosmos) if rpc.parse::<tendermint_rpc::Url>().is_err() => {
+                problems.push("rpc: invalid URL".to_string())
+            }
+            _ => {}
+        }
+        problems
+    }
+
+    /// The fields of the adapter's config that no field took.
+    pub fn unknown_fields(&self) -> impl Iterator<Item = &str> {
+        self.unknown.keys().map(String::as_str)
+    }
+}
+
+pub fn strs(values: &[String]) -> Vec<&str> {
+    values.iter().map(String::as_str).collect()
+}
+
+pub fn check_evm_address(problems: &mut Vec<String>, field: &str, value: &str) {
+    if value.parse::<ethers::types::Address>().is_err() {
+        problems.push(format!("{}: invalid address", field));
+    }
+}
+
+pub fn check_evm_addresses(problems: &mut Vec<String>, field: &str, values: &[String]) {
+    if values.is_empty() {
+        problems.push(format!("{}: no addresses", field));
+    }
+    for (i, value) in values.iter().enumerate() {
+        check_evm_address(problems, &format!("{}[{}]", field, i), value);
+    }
+}
+
+/// Checks a bech32 Cosmos address, such as `cosmos1...` or a contract.
+pub fn check_cosmos_address(problems: &mut Vec<String>, field: &str, value: &str) {
+    match subtle_encoding::bech32::decode(value) {
+        Ok((prefix, _)) if !prefix.is_empty() => {}
+        _ => problems.push(format!("{}: invalid address", field)),
+    }
+}
+
+pub fn check_cosmos_addresses(problems: &mut Vec<String>, field: &str, values: &[String]) {
+    if values.is_empty() {
+        problems.push(format!("{}: no addresses", field));
+    }
+    for (i, value) in values.iter().enumerate() {
+        check_cosmos_address(problems, &format!("{}[{}]", field, i), value);
+    }
+}
+
+pub fn check_url(problems: &mut Vec<String>, field: &str, value: &str) {
+    if reqwest::Url::parse(value).is_err() {
+        problems.push(format!("{}: invalid URL", field));
+    }
+}
+
+/// A token amount in base units together with the token's decimals. It stays
+/// exact until it is converted for export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amount {
+    pub raw: U256,
+    pub decimals: u8,
+}
+
+#[derive(Debug)]
+pub enum AmountError {
+    Parse(String),
+    Overflow { amount: Amount, unit: ExportUnit },
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Parse(value) => write!(f, "invalid amount: {}", value),
+            AmountError::Overflow { amount, unit } => write!(
+                f,
+                "amount {} with {} decimals overflows when exported as {:?}",
+                amount.raw, amount.decimals, unit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// How amounts are exported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportUnit {
+    /// Base units, e.g. wei or satoshis.
+    Raw,
+    /// Whole tokens, with the fraction kept as a float.
+    #[default]
+    Tokens,
+    /// An integer count of 10^-n tokens, truncated; `{ fixed = 2 }` exports cents.
+    Fixed(u8),
+}
+
+impl Amount {
+    pub fn new(raw: U256, decimals: u8) -> Self {
+        Self { raw, decimals }
+    }
+
+    pub fn from_dec_str(value: &str, decimals: u8) -> Result<Self, AmountError> {
+        let raw = U256::from_dec_str(value).map_err(|_| AmountError::Parse(value.to_string()))?;
+        Ok(Self::new(raw, decimals))
+    }
+
+    /// The amount as an integer count of 10^-`digits` tokens, truncated toward zero.
+    pub fn to_fixed(&self, digits: u8) -> Result<U256, AmountError> {
+        let overflow = || AmountError::Overflow {
+            amount: *self,
+            unit: ExportUnit::Fixed(digits),
+        };
+        if digits >= self.decimals {
+            let scale = pow10(digits - self.decimals).ok_or_else(overflow)?;
+            self.raw.checked_mul(scale).ok_or_else(overflow)
+        } else {
+            // 10^n for n > 77 exceeds U256, so the result is already zero.
+            Ok(pow10(self.decimals - digits)
+                .map(|scale| self.raw / scale)
+                .unwrap_or_default())
+        }
+    }
+
+    /// Whole tokens as an exact decimal, e.g. `1.50` for 150 with 2 decimals.
+    pub fn to_decimal_string(&self) -> String {
+        let digits = self.raw.to_string();
+        let decimals = self.decimals as usize;
+        if decimals == 0 {
+            digits
+        } else if digits.len() > decimals {
+            let (whole, fraction) = digits.split_at(digits.len() - decimals);
+            format!("{}.{}", whole, fraction)
+        } else {
+            format!("0.{}{}", "0".repeat(decimals - digits.len()), digits)
+        }
+    }
+
+    /// Whole tokens as the nearest `f64`.
+    pub fn to_tokens(&self) -> f64 {
+        // A plain decimal literal always parses.
+        self.to_decimal_string().parse().unwrap()
+    }
+
+    pub fn export(&self, unit: ExportUnit) -> Result<f64, AmountError> {
+        Ok(match unit {
+            ExportUnit::Raw => u256_to_f64(self.raw),
+            ExportUnit::Tokens => self.to_tokens(),
+            ExportUnit::Fixed(digits) => u256_to_f64(self.to_fixed(digits)?),
+        })
+    }
+}
+
+fn pow10(exponent: u8) -> Option<U256> {
+    U256::from(10).checked_pow(U256::from(exponent))
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    // A decimal integer always parses, rounding to the nearest f64.
+    value.to_string().parse().unwrap()
+}
+
+/// Metric families shared by all adapters. Every series also carries the
+/// `adapter` and `chain` labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Family {
+    /// `symbol` is the token's ticker, empty where the adapter does not know it.
+    /// `entity`, `purpose` and `tags` come from the address book, and are
+    /// appended by the runtime.
+    Balance,
+    /// A `Balance` times the USD price of its asset.
+    BalanceValueUsd,
+    InterestRate,
+    AvailableLiquidity,
+    IdleLiquidity,
+    ContractValue,
+    /// Price of `ticker` from an oracle, in its quote asset.
+    Price,
+    PriceConfidence,
+    PricePublishTime,
+    /// Round id of a Chainlink price within its aggregator's phase.
+    PriceRound,
+    /// Seconds between a price's last update and the snapshot block.
+    PriceAge,
+    /// 1 when a price is older than its feed's heartbeat.
+    PriceStale,
+    SnapshotBlockHeight,
+    SnapshotBlockTimestamp,
+    /// Always 1; `chain_id` is the id the adapter's rpc was verified to serve.
+    ChainInfo,
+}
+
+impl Family {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Family::Balance => "pex_balance",
+            Family::BalanceValueUsd => "pex_balance_value_usd",
+            Family::InterestRate => "pex_interest_rate",
+            Family::AvailableLiquidity => "pex_available_liquidity",
+            Family::IdleLiquidity => "pex_idle_liquidity",
+            Family::ContractValue => "pex_contract_value",
+            Family::Price => "pex_price",
+            Family::PriceConfidence => "pex_price_confidence",
+            Family::PricePublishTime => "pex_price_publish_timestamp_seconds",
+            Family::PriceRound => "pex_price_round_id",
+            Family::PriceAge => "pex_price_age_seconds",
+            Family::PriceStale => "pex_price_stale",
+            Family::SnapshotBlockHeight => "pex_snapshot_block_height",
+            Family::SnapshotBlockTimestamp => "pex_snapshot_block_timestamp_seconds",
+            Family::ChainInfo => "pex_chain_info",
+        }
+    }
+
+    pub fn help(&self) -> &'static str {
+        match self {
+            Family::Balance => "Balance of an address",
+            Family::BalanceValueUsd => "Balance of an address valued in USD",
+            Family::InterestRate => "Annualized supply interest rate of a market",
+            Family::AvailableLiquidity => "Liquidity that can be withdrawn from a vault",
+            Family::IdleLiquidity => "Liquidity of a vault that is not allocated to any market",
+            Family::ContractValue => "Value extracted from a contract query",
+            Family::Price => "Price reported by an oracle",
+            Family::PriceConfidence => "Confidence interval of an oracle price",
+            Family::PricePublishTime => "Time an oracle price was published",
+            Family::PriceRound => "Round an oracle price was reported in",
+            Family::PriceAge => "Age of an oracle price at the snapshot block",
+            Family::PriceStale => "Whether an oracle price is older than its heartbeat",
+            Family::SnapshotBlockHeight => "Block height the adapter's last values were read at",
+            Family::SnapshotBlockTimestamp => {
+                "Timestamp of the block the adapter's last values were read at"
+            }
+            Family::ChainInfo => "Chain id the adapter's rpc was verified to serve",
+        }
+    }
+
+    pub fn labels(&self) -> &'static [&'static str] {
+        match self {
+            Family::Balance | Family::BalanceValueUsd => {
+                &["address", "asset", "symbol", "entity", "purpose", "tags"]
+            }
+            Family::InterestRate => &["market"],
+            Family::AvailableLiquidity | Family::IdleLiquidity => &["vault"],
+            Family::ContractValue => &["contract", "key"],
+            // Chainlink round ids are the phase in the high bits over the
+            // round within the phase, which alone fits a gauge.
+            Family::PriceRound => &["ticker", "phase"],
+            Family::Price
+            | Family::PriceConfidence
+            | Family::PricePublishTime
+            | Family::PriceAge
+            | Family::PriceStale => &["ticker"],
+            Family::SnapshotBlockHeight | Family::SnapshotBlockTimestamp => &[],
+            Family::ChainInfo => &["chain_id"],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(raw: u64, decimals: u8) -> Amount {
+        Amount::new(U256::from(raw), decimals)
+    }
+
+    #[test]
+    fn to_fixed_scales_up() {
+        assert_eq!(amount(150, 2).to_fixed(4).unwrap(), U256::from(15000));
+        assert_eq!(amount(150, 2).to_fixed(2).unwrap(), U256::from(150));
+    }
+
+    #[test]
+    fn to_fixed_truncates_toward_zero() {
+        assert_eq!(amount(1999, 3).to_fixed(1).unwrap(), U256::from(19));
+        assert_eq!(amount(999, 3).to_fixed(0).unwrap(), U256::zero());
+        // 10^78 does not fit in a U256, but the result is zero all the same.
+        assert_eq!(amount(u64::MAX, 78).to_fixed(0).unwrap(), U256::zero());
+    }
+
+    #[test]
+    fn to_fixed_overflows() {
+        let max = Amount::new(U256::MAX, 0);
+        assert!(matches!(
+            max.to_fixed(1),
+            Err(AmountError::Overflow {
+                unit: ExportUnit::Fixed(1),
+                ..
+            })
+        ));
+        assert!(amount(1, 0).to_fixed(78).is_err());
+        assert_eq!(max.to_fixed(0).unwrap(), U256::MAX);
+    }
+
+    #[test]
+    fn to_decimal_string_without_decimals() {
+        assert_eq!(amount(0, 0).to_decimal_string(), "0");
+        assert_eq!(amount(1234, 0).to_decimal_string(), "1234");
+    }
+
+    #[test]
+    fn to_decimal_string_inserts_the_point() {
+        assert_eq!(amount(150, 2).to_decimal_string(), "1.50");
+        assert_eq!(amount(1_000_000, 6).to_decimal_string(), "1.000000");
+    }
+
+    #[test]
+    fn to_decimal_string_pads_short_values() {
+        assert_eq!(amount(5, 3).to_decimal_string(), "0.005");
+        assert_eq!(amount(123, 3).to_decimal_string(), "0.123");
+        assert_eq!(amount(0, 2).to_decimal_string(), "0.00");
+    }
+
+    #[test]
+    fn export_in_each_unit() {
+        let amount = amount(1_234_567, 6);
+        assert_eq!(amount.export(ExportUnit::Raw).unwrap(), 1_234_567.0);
+        assert_eq!(amount.export(ExportUnit::Tokens).unwrap(), 1.234567);
+        assert_eq!(amount.export(ExportUnit::Fixed(2)).unwrap(), 123.0);
+        assert_eq!(amount.export(ExportUnit::Fixed(8)).unwrap(), 123_456_700.0);
+    }
+
+    #[test]
+    fn export_reports_overflow() {
+        let max = Amount::new(U256::MAX, 0);
+        assert!(max.export(ExportUnit::Raw).is_ok());
+        assert!(max.export(ExportUnit::Fixed(1)).is_err());
+    }
+}
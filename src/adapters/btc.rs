@@ -1,7 +1,42 @@
-use super::base::MetricsAdapter;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use super::base::{AdapterConfig, Amount, Family, Measurement, MetricsAdapter, Observation, strs};
+use crate::chains::Chains;
+use serde::Deserialize;
+
+const BTC_DECIMALS: u8 = 8;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BtcConfig {
+    pub addresses: Vec<String>,
+    pub chain: Option<String>,
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for BtcConfig {
+    const DEFAULT_CHAIN: &'static str = "bitcoin";
+
+    fn validate(&self) -> Vec<String> {
+        if self.addresses.is_empty() {
+            return vec!["addresses: no addresses".to_string()];
+        }
+        Vec::new()
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        _chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            BTCAdapter::new(name, strs(&self.addresses)).await?,
+        ))
+    }
+
+    async fn check(&self, _chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let url = "https://blockchain.info/q/getblockcount";
+        let height = reqwest::get(url).await?.error_for_status()?.text().await?;
+        Ok(format!("block height {}", height.trim()))
+    }
+}
 
 pub struct BTCAdapter {
     name: String,
@@ -14,32 +49,13 @@ impl MetricsAdapter for BTCAdapter {
         &self.name
     }
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut storage = metrics.lock().await;
-        self.update_balances(&mut storage).await?;
-        Ok(())
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        self.fetch_balances().await
     }
 }
 
 impl BTCAdapter {
-    pub async fn new(
-        name: &str,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-        addresses: Vec<&str>,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut storage = metrics.lock().await;
-        for addr in addresses.clone() {
-            let key = format!("{}_balance_{}", name, addr);
-            let value = super::base::Value::Int(prometheus::IntGauge::new(
-                &key,
-                format!("Value of {}", key),
-            )?);
-            storage.insert(key, value);
-        }
-
+    pub async fn new(name: &str, addresses: Vec<&str>) -> Result<Self, Box<dyn std::error::Error>> {
         Ok(Self {
             name: name.to_string(),
             addresses: addresses
@@ -50,22 +66,19 @@ impl BTCAdapter {
         })
     }
 
-    async fn update_balances(
-        &mut self,
-        storage: &mut HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn fetch_balances(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let mut observations = Vec::new();
         for addr in self.addresses.clone() {
             let url = format!("https://blockchain.info/q/addressbalance/{}", &addr);
-            let key = "balance_".to_string() + &addr;
             let response = reqwest::get(&url).await?.text().await?;
-            let balance_satoshis: u64 = response.trim().parse()?;
-            match storage.get(&self.get_key(&key)) {
-                Some(super::base::Value::Int(v)) => {
-                    v.set(balance_satoshis as i64);
-                }
-                _ => unreachable!(),
-            }
+            let balance = Amount::from_dec_str(response.trim(), BTC_DECIMALS)?;
+            observations.push(Observation::new(
+                Family::Balance,
+                &[&addr, "BTC", "BTC"],
+                Measurement::Amount(balance),
+                format!("balance_{}", addr),
+            ));
         }
-        Ok(())
+        Ok(observations)
     }
 }
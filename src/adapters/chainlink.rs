@@ -0,0 +1,205 @@
+use crate::chains::{Chains, EvmProvider};
+use crate::check::check_evm_contracts;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::base::{
+    AdapterConfig, EndpointConfig, Family, Measurement, MetricsAdapter, Observation,
+    check_evm_address,
+};
+use super::snapshot::Snapshot;
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+use ethers::types::{Address, BlockId, I256, U256};
+use serde::Deserialize;
+
+const AGGREGATOR_ABI: &[&str] = &[
+    "function decimals() external view returns (uint8)",
+    "function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainlinkConfig {
+    pub aggregators: Vec<AggregatorConfig>,
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AggregatorConfig {
+    pub ticker: String,
+    pub address: String,
+    pub heartbeat: u64, // in seconds, the longest the feed goes without an update
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for ChainlinkConfig {
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.aggregators.is_empty() {
+            problems.push("aggregators: no aggregators".to_string());
+        }
+        let mut tickers = HashSet::new();
+        for (i, aggregator) in self.aggregators.iter().enumerate() {
+            if !tickers.insert(&aggregator.ticker) {
+                problems.push(format!(
+                    "aggregators[{}].ticker: {} is listed twice",
+                    i, aggregator.ticker
+                ));
+            }
+            check_evm_address(
+                &mut problems,
+                &format!("aggregators[{}].address", i),
+                &aggregator.address,
+            );
+            if aggregator.heartbeat == 0 {
+                problems.push(format!("aggregators[{}].heartbeat: must be at least 1", i));
+            }
+        }
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        let aggregators = self
+            .aggregators
+            .iter()
+            .map(|aggregator| {
+                Ok(Aggregator {
+                    ticker: aggregator.ticker.clone(),
+                    address: aggregator.address.parse()?,
+                    heartbeat: aggregator.heartbeat,
+                })
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+        Ok(Box::new(ChainlinkAdapter::new(
+            name,
+            aggregators,
+            chains.evm(self.endpoint.as_endpoint())?,
+        )))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let client = chains.evm(self.endpoint.as_endpoint())?;
+        let aggregators = self.aggregators.iter().map(|a| a.address.as_str());
+        check_evm_contracts(client, aggregators).await
+    }
+}
+
+pub struct Aggregator {
+    ticker: String,
+    address: Address,
+    heartbeat: u64,
+}
+
+pub struct ChainlinkAdapter {
+    name: String,
+    aggregators: Vec<Aggregator>,
+    client: Arc<EvmProvider>,
+}
+
+#[async_trait::async_trait]
+impl MetricsAdapter for ChainlinkAdapter {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::evm(&self.client).await?;
+        let mut observations = snapshot.observations();
+        for aggregator in &self.aggregators {
+            let ticker = aggregator.ticker.as_str();
+            let round = latest_round(
+                Arc::clone(&self.client),
+                aggregator.address,
+                Some(snapshot.block()),
+            )
+            .await
+            .map_err(|e| format!("{}: {}", ticker, e))?;
+            let age = snapshot.timestamp.saturating_sub(round.updated_at);
+
+            observations.push(Observation::new(
+                Family::Price,
+                &[ticker],
+                Measurement::Float(round.price),
+                format!("price_{}", ticker),
+            ));
+            let phase = (round.id >> 64).to_string();
+            let round_in_phase = i64::try_from(round.id & u128::from(u64::MAX))
+                .map_err(|_| format!("{}: round {} is out of range", ticker, round.id))?;
+            observations.push(Observation::new(
+                Family::PriceRound,
+                &[ticker, &phase],
+                Measurement::Int(round_in_phase),
+                format!("price_round_{}", ticker),
+            ));
+            observations.push(Observation::new(
+                Family::PricePublishTime,
+                &[ticker],
+                Measurement::Int(round.updated_at as i64),
+                format!("price_publish_time_{}", ticker),
+            ));
+            observations.push(Observation::new(
+                Family::PriceAge,
+                &[ticker],
+                Measurement::Int(age as i64),
+                format!("price_age_{}", ticker),
+            ));
+            observations.push(Observation::new(
+                Family::PriceStale,
+                &[ticker],
+                Measurement::Int((age > aggregator.heartbeat) as i64),
+                format!("price_stale_{}", ticker),
+            ));
+        }
+        Ok(observations)
+    }
+}
+
+impl ChainlinkAdapter {
+    pub fn new(name: &str, aggregators: Vec<Aggregator>, client: Arc<EvmProvider>) -> Self {
+        Self {
+            name: name.to_string(),
+            aggregators,
+            client,
+        }
+    }
+}
+
+/// The latest answer of a Chainlink aggregator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Round {
+    pub id: u128,
+    pub price: f64,      // scaled by the aggregator's decimals
+    pub updated_at: u64, // in seconds since the epoch
+}
+
+/// Reads the latest round of `aggregator`, at `block` if given.
+pub async fn latest_round(
+    client: Arc<EvmProvider>,
+    aggregator: Address,
+    block: Option<BlockId>,
+) -> Result<Round, Box<dyn std::error::Error>> {
+    let contract = Contract::new(aggregator, parse_abi(AGGREGATOR_ABI)?, client);
+    let mut decimals = contract.method::<_, u8>("decimals", ())?;
+    let mut latest = contract.method::<_, (u128, I256, U256, U256, u128)>("latestRoundData", ())?;
+    if let Some(block) = block {
+        decimals = decimals.block(block);
+        latest = latest.block(block);
+    }
+    let decimals = decimals.call().await?;
+    let (id, answer, _, updated_at, _) = latest.call().await?;
+    Ok(Round {
+        id,
+        price: answer.to_string().parse::<f64>()? / 10f64.powi(decimals as i32),
+        updated_at: u64::try_from(updated_at)
+            .map_err(|_| format!("updatedAt {} is out of range", updated_at))?,
+    })
+}
@@ -1,19 +1,74 @@
-use super::base::MetricsAdapter;
+use crate::chains::{Chains, EvmProvider};
+use crate::check::check_evm;
+
+use super::base::{
+    AdapterConfig, Amount, EndpointConfig, ExportUnit, Family, Measurement, MetricsAdapter,
+    Observation, check_evm_address, check_evm_addresses, strs,
+};
+use super::snapshot::Snapshot;
 use ethers::providers::Middleware;
 use ethers::{abi::Abi, types::U256};
-use std::collections::HashMap;
+use serde::Deserialize;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+
+const SAMPLE_BLOCKS: u64 = 1000;
+const SECONDS_PER_YEAR: f64 = (60 * 60 * 24 * 365) as f64;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompoundConfig {
+    pub addresses: Vec<String>,
+    pub contract: String,
+    pub decimals: u8,
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for CompoundConfig {
+    // Cents, from scaling by `decimals - 2`.
+    const LEGACY_UNIT: ExportUnit = ExportUnit::Fixed(2);
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_evm_addresses(&mut problems, "addresses", &self.addresses);
+        check_evm_address(&mut problems, "contract", &self.contract);
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            CompoundAdapter::new(
+                name,
+                strs(&self.addresses),
+                &self.contract,
+                chains.evm(self.endpoint.as_endpoint())?,
+                self.decimals,
+            )
+            .await?,
+        ))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let client = chains.evm(self.endpoint.as_endpoint())?;
+        check_evm(client, Some(&self.contract)).await
+    }
+}
 
 pub struct CompoundAdapter {
     name: String,
     addresses: Vec<String>,
+    market: String,
     decimals: u8,
-    client: Arc<ethers::prelude::Provider<ethers::providers::Http>>,
-    contract: ethers::contract::ContractInstance<
-        Arc<ethers::providers::Provider<ethers::providers::Http>>,
-        ethers::providers::Provider<ethers::providers::Http>,
-    >,
+    client: Arc<EvmProvider>,
+    contract: ethers::contract::ContractInstance<Arc<EvmProvider>, EvmProvider>,
 }
 
 #[async_trait::async_trait]
@@ -22,47 +77,29 @@ impl MetricsAdapter for CompoundAdapter {
         &self.name
     }
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut storage = metrics.lock().await;
-        self.update_balances(&mut storage).await?;
-        self.update_general_info(&mut storage).await?;
-        Ok(())
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::evm(&self.contract.client()).await?;
+        let mut observations = self.fetch_balances(&snapshot).await?;
+        observations.push(self.fetch_general_info(&snapshot).await?);
+        observations.extend(snapshot.observations());
+        Ok(observations)
     }
 }
 
 impl CompoundAdapter {
     pub async fn new(
         name: &str,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
         addresses: Vec<&str>,
         contract: &str,
-        rpc: &str,
+        client: Arc<EvmProvider>,
         decimals: u8,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let abi: Abi = serde_json::from_str(
             r#"[{"inputs":[{"internalType":"address","name":"underlying_","type":"address"},{"internalType":"contract ComptrollerInterface","name":"comptroller_","type":"address"},{"internalType":"contract InterestRateModel","name":"interestRateModel_","type":"address"},{"internalType":"uint256","name":"initialExchangeRateMantissa_","type":"uint256"},{"internalType":"string","name":"name_","type":"string"},{"internalType":"string","name":"symbol_","type":"string"},{"internalType":"uint8","name":"decimals_","type":"uint8"},{"internalType":"address payable","name":"admin_","type":"address"},{"internalType":"address","name":"implementation_","type":"address"},{"internalType":"bytes","name":"becomeImplementationData","type":"bytes"}],"payable":false,"stateMutability":"nonpayable","type":"constructor"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"uint256","name":"cashPrior","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"interestAccumulated","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"borrowIndex","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"totalBorrows","type":"uint256"}],"name":"AccrueInterest","type":"event"},{"anonymous":false,"inputs":[{"indexed":true,"internalType":"address","name":"owner","type":"address"},{"indexed":true,"internalType":"address","name":"spender","type":"address"},{"indexed":false,"internalType":"uint256","name":"amount","type":"uint256"}],"name":"Approval","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"borrower","type":"address"},{"indexed":false,"internalType":"uint256","name":"borrowAmount","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"accountBorrows","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"totalBorrows","type":"uint256"}],"name":"Borrow","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"uint256","name":"error","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"info","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"detail","type":"uint256"}],"name":"Failure","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"liquidator","type":"address"},{"indexed":false,"internalType":"address","name":"borrower","type":"address"},{"indexed":false,"internalType":"uint256","name":"repayAmount","type":"uint256"},{"indexed":false,"internalType":"address","name":"cTokenCollateral","type":"address"},{"indexed":false,"internalType":"uint256","name":"seizeTokens","type":"uint256"}],"name":"LiquidateBorrow","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"minter","type":"address"},{"indexed":false,"internalType":"uint256","name":"mintAmount","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"mintTokens","type":"uint256"}],"name":"Mint","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"oldAdmin","type":"address"},{"indexed":false,"internalType":"address","name":"newAdmin","type":"address"}],"name":"NewAdmin","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"contract ComptrollerInterface","name":"oldComptroller","type":"address"},{"indexed":false,"internalType":"contract ComptrollerInterface","name":"newComptroller","type":"address"}],"name":"NewComptroller","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"oldImplementation","type":"address"},{"indexed":false,"internalType":"address","name":"newImplementation","type":"address"}],"name":"NewImplementation","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"contract InterestRateModel","name":"oldInterestRateModel","type":"address"},{"indexed":false,"internalType":"contract InterestRateModel","name":"newInterestRateModel","type":"address"}],"name":"NewMarketInterestRateModel","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"oldPendingAdmin","type":"address"},{"indexed":false,"internalType":"address","name":"newPendingAdmin","type":"address"}],"name":"NewPendingAdmin","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"uint256","name":"oldReserveFactorMantissa","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"newReserveFactorMantissa","type":"uint256"}],"name":"NewReserveFactor","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"redeemer","type":"address"},{"indexed":false,"internalType":"uint256","name":"redeemAmount","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"redeemTokens","type":"uint256"}],"name":"Redeem","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"payer","type":"address"},{"indexed":false,"internalType":"address","name":"borrower","type":"address"},{"indexed":false,"internalType":"uint256","name":"repayAmount","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"accountBorrows","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"totalBorrows","type":"uint256"}],"name":"RepayBorrow","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"benefactor","type":"address"},{"indexed":false,"internalType":"uint256","name":"addAmount","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"newTotalReserves","type":"uint256"}],"name":"ReservesAdded","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"internalType":"address","name":"admin","type":"address"},{"indexed":false,"internalType":"uint256","name":"reduceAmount","type":"uint256"},{"indexed":false,"internalType":"uint256","name":"newTotalReserves","type":"uint256"}],"name":"ReservesReduced","type":"event"},{"anonymous":false,"inputs":[{"indexed":true,"internalType":"address","name":"from","type":"address"},{"indexed":true,"internalType":"address","name":"to","type":"address"},{"indexed":false,"internalType":"uint256","name":"amount","type":"uint256"}],"name":"Transfer","type":"event"},{"payable":true,"stateMutability":"payable","type":"fallback"},{"constant":false,"inputs":[],"name":"_acceptAdmin","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"addAmount","type":"uint256"}],"name":"_addReserves","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"reduceAmount","type":"uint256"}],"name":"_reduceReserves","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"contract ComptrollerInterface","name":"newComptroller","type":"address"}],"name":"_setComptroller","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"implementation_","type":"address"},{"internalType":"bool","name":"allowResign","type":"bool"},{"internalType":"bytes","name":"becomeImplementationData","type":"bytes"}],"name":"_setImplementation","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"contract InterestRateModel","name":"newInterestRateModel","type":"address"}],"name":"_setInterestRateModel","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"address payable","name":"newPendingAdmin","type":"address"}],"name":"_setPendingAdmin","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"newReserveFactorMantissa","type":"uint256"}],"name":"_setReserveFactor","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"accrualBlockNumber","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[],"name":"accrueInterest","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"admin","outputs":[{"internalType":"address payable","name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"internalType":"address","name":"owner","type":"address"},{"internalType":"address","name":"spender","type":"address"}],"name":"allowance","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"spender","type":"address"},{"internalType":"uint256","name":"amount","type":"uint256"}],"name":"approve","outputs":[{"internalType":"bool","name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"internalType":"address","name":"owner","type":"address"}],"name":"balanceOf","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"owner","type":"address"}],"name":"balanceOfUnderlying","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"borrowAmount","type":"uint256"}],"name":"borrow","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"account","type":"address"}],"name":"borrowBalanceCurrent","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"internalType":"address","name":"account","type":"address"}],"name":"borrowBalanceStored","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"borrowIndex","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"borrowRatePerBlock","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"comptroller","outputs":[{"internalType":"contract ComptrollerInterface","name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"decimals","outputs":[{"internalType":"uint8","name":"","type":"uint8"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"internalType":"bytes","name":"data","type":"bytes"}],"name":"delegateToImplementation","outputs":[{"internalType":"bytes","name":"","type":"bytes"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"internalType":"bytes","name":"data","type":"bytes"}],"name":"delegateToViewImplementation","outputs":[{"internalType":"bytes","name":"","type":"bytes"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[],"name":"exchangeRateCurrent","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"exchangeRateStored","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"internalType":"address","name":"account","type":"address"}],"name":"getAccountSnapshot","outputs":[{"internalType":"uint256","name":"","type":"uint256"},{"internalType":"uint256","name":"","type":"uint256"},{"internalType":"uint256","name":"","type":"uint256"},{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"getCash","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"implementation","outputs":[{"internalType":"address","name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"interestRateModel","outputs":[{"internalType":"contract InterestRateModel","name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"isCToken","outputs":[{"internalType":"bool","name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"borrower","type":"address"},{"internalType":"uint256","name":"repayAmount","type":"uint256"},{"internalType":"contract CTokenInterface","name":"cTokenCollateral","type":"address"}],"name":"liquidateBorrow","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"mintAmount","type":"uint256"}],"name":"mint","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"name","outputs":[{"internalType":"string","name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"pendingAdmin","outputs":[{"internalType":"address payable","name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"redeemTokens","type":"uint256"}],"name":"redeem","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"redeemAmount","type":"uint256"}],"name":"redeemUnderlying","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"uint256","name":"repayAmount","type":"uint256"}],"name":"repayBorrow","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"reserveFactorMantissa","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"liquidator","type":"address"},{"internalType":"address","name":"borrower","type":"address"},{"internalType":"uint256","name":"seizeTokens","type":"uint256"}],"name":"seize","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"supplyRatePerBlock","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"symbol","outputs":[{"internalType":"string","name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"totalBorrows","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[],"name":"totalBorrowsCurrent","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"totalReserves","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"totalSupply","outputs":[{"internalType":"uint256","name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"dst","type":"address"},{"internalType":"uint256","name":"amount","type":"uint256"}],"name":"transfer","outputs":[{"internalType":"bool","name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"internalType":"address","name":"src","type":"address"},{"internalType":"address","name":"dst","type":"address"},{"internalType":"uint256","name":"amount","type":"uint256"}],"name":"transferFrom","outputs":[{"internalType":"bool","name":"","type":"bool"}],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"underlying","outputs":[{"internalType":"address","name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"}]"#,
         )?;
-        let provider = ethers::prelude::Provider::<ethers::providers::Http>::try_from(rpc)?;
-        let client = Arc::new(provider);
         let token_address: ethers::types::Address = contract.parse()?;
 
-        let contract = ethers::contract::Contract::new(token_address, abi, client.clone());
-        let mut storage = metrics.lock().await;
-        let key = format!("{}_interest", name);
-        let value =
-            super::base::Value::Float(prometheus::Gauge::new(&key, format!("Value of {}", key))?);
-        storage.insert(key, value);
-        for addr in addresses.clone() {
-            let key = format!("{}_balance_{}", name, addr);
-            let value = super::base::Value::Int(prometheus::IntGauge::new(
-                &key,
-                format!("Value of {}", key),
-            )?);
-            storage.insert(key, value);
-        }
+        let market = ethers::contract::Contract::new(token_address, abi, client.clone());
 
         Ok(Self {
             client,
@@ -71,67 +108,71 @@ impl CompoundAdapter {
                 .iter()
                 .map(|addr| addr.parse().unwrap())
                 .collect(),
-            contract,
+            market: contract.to_string(),
+            contract: market,
             name: name.to_string(),
             decimals,
         })
     }
 
-    async fn update_balances(
-        &mut self,
-        storage: &mut HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn fetch_balances(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let mut observations = Vec::new();
         for addr in self.addresses.clone() {
             let address: ethers::types::Address = addr.parse()?;
             let balance: U256 = self
                 .contract
                 .method::<_, U256>("balanceOfUnderlying", address)?
+                .block(snapshot.block())
                 .call()
-                .await?
-                .checked_div(U256::from(10).pow(self.decimals.into()))
-                .unwrap();
-            let value = storage.get(&self.get_key(&format!("balance_{}", addr)));
-            match value {
-                Some(super::base::Value::Int(v)) => {
-                    v.set(balance.as_u64() as i64);
-                }
-                _ => unreachable!(),
-            }
+                .await?;
+            observations.push(Observation::new(
+                Family::Balance,
+                &[&addr, &self.market, ""],
+                Measurement::Amount(Amount::new(balance, self.decimals)),
+                format!("balance_{}", addr),
+            ));
         }
-        Ok(())
+        Ok(observations)
     }
 
-    async fn update_general_info(
-        &mut self,
-        storage: &mut HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let current_unix_timestamp = U256::from(chrono::Utc::now().timestamp());
-        let current_block = U256::from(self.client.get_block_number().await?.as_u64());
+    async fn fetch_general_info(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Observation, Box<dyn std::error::Error>> {
+        // The block time is measured over the last `SAMPLE_BLOCKS` blocks.
+        let far_height = snapshot
+            .height
+            .checked_sub(SAMPLE_BLOCKS)
+            .ok_or_else(|| format!("fewer than {} blocks to time", SAMPLE_BLOCKS))?;
         let far_block = self
             .client
-            .get_block(current_block.as_u64() - 1000)
+            .get_block(far_height)
             .await?
-            .unwrap();
-        let far_unix_timestamp = far_block.timestamp;
-        let blocks_in_year = U256::from(60 * 60 * 24 * 365)
-            / ((current_unix_timestamp - far_unix_timestamp) / U256::from(1000));
+            .ok_or_else(|| format!("block {} not found", far_height))?;
+        let elapsed = U256::from(snapshot.timestamp)
+            .checked_sub(far_block.timestamp)
+            .filter(|elapsed| !elapsed.is_zero())
+            .ok_or_else(|| format!("no time passed since block {}", far_height))?;
+        let block_time = u64::try_from(elapsed)? as f64 / SAMPLE_BLOCKS as f64;
+        let blocks_in_year = SECONDS_PER_YEAR / block_time;
 
         let supply_rate: U256 = self
             .contract
             .method::<_, U256>("supplyRatePerBlock", ())?
+            .block(snapshot.block())
             .call()
             .await?;
 
-        let p: f64 = (1.0 + supply_rate.as_u64() as f64 / 10f64.powi(18))
-            .powi(blocks_in_year.as_u64() as i32)
-            - 1.0;
-        let value = storage.get(&self.get_key("interest"));
-        match value {
-            Some(super::base::Value::Float(v)) => {
-                v.set(p);
-            }
-            _ => unreachable!(),
-        }
-        Ok(())
+        // The rate per block is a fraction with 18 decimals.
+        let p: f64 = (1.0 + Amount::new(supply_rate, 18).to_tokens()).powf(blocks_in_year) - 1.0;
+        Ok(Observation::new(
+            Family::InterestRate,
+            &[&self.market],
+            Measurement::Float(p),
+            "interest".to_string(),
+        ))
     }
 }
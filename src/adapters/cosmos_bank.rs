@@ -1,19 +1,74 @@
 use bytes::Bytes;
 use cosmos_sdk_proto::cosmos::base::query::v1beta1::PageRequest;
 use cosmos_sdk_proto::traits::Message;
-use std::collections::HashMap;
-use std::sync::Arc;
 use std::vec;
 use tendermint_rpc::Client;
-use tokio::sync::Mutex;
 
-use super::base::MetricsAdapter;
+use crate::chains::{Chains, CosmosClient, Network};
+use crate::check::check_cosmos;
+
+use super::base::{
+    AdapterConfig, Amount, EndpointConfig, Family, Measurement, MetricsAdapter, Observation,
+    check_cosmos_addresses, strs,
+};
+use super::snapshot::Snapshot;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CosmosBankConfig {
+    pub addresses: Vec<String>,
+    pub denoms: Vec<String>,
+    pub decimals: u8, // applies to every denom, as the chain does not report it
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for CosmosBankConfig {
+    const DEFAULT_CHAIN: &'static str = "cosmos";
+    const NETWORK: Network = Network::Cosmos;
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_cosmos_addresses(&mut problems, "addresses", &self.addresses);
+        if self.denoms.is_empty() {
+            problems.push("denoms: no denoms".to_string());
+        }
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            CosmosBankAdapter::new(
+                name,
+                strs(&self.addresses),
+                chains.cosmos(self.endpoint.as_endpoint())?,
+                strs(&self.denoms),
+                self.decimals,
+            )
+            .await?,
+        ))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        check_cosmos(&chains.cosmos(self.endpoint.as_endpoint())?, None).await
+    }
+}
 
 pub struct CosmosBankAdapter {
     name: String,
     addresses: Vec<String>,
-    client: tendermint_rpc::HttpClient,
+    client: CosmosClient,
     denoms: Vec<String>,
+    decimals: u8,
 }
 
 #[async_trait::async_trait]
@@ -22,38 +77,22 @@ impl MetricsAdapter for CosmosBankAdapter {
         &self.name
     }
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut storage = metrics.lock().await;
-        self.update_balances(&mut storage).await?;
-        Ok(())
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::cosmos(&self.client).await?;
+        let mut observations = self.fetch_balances(&snapshot).await?;
+        observations.extend(snapshot.observations());
+        Ok(observations)
     }
 }
 
 impl CosmosBankAdapter {
     pub async fn new(
         name: &str,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
         addresses: Vec<&str>,
-        rpc: &str,
+        client: CosmosClient,
         denoms: Vec<&str>,
+        decimals: u8,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let client = tendermint_rpc::HttpClient::builder(rpc.parse()?).build()?;
-
-        let mut storage = metrics.lock().await;
-        for addr in addresses.clone() {
-            for denom in denoms.clone() {
-                let key = format!("{}_balance_{}_{}", name, addr, denom);
-                let value = super::base::Value::Int(prometheus::IntGauge::new(
-                    &key,
-                    format!("Value of {}", key),
-                )?);
-                storage.insert(key, value);
-            }
-        }
-
         Ok(Self {
             name: name.to_string(),
             addresses: addresses
@@ -63,13 +102,16 @@ impl CosmosBankAdapter {
                 .collect(),
             client,
             denoms: denoms.iter().map(|d| d.to_string()).collect(),
+            decimals,
         })
     }
 
-    async fn update_balances(
-        &mut self,
-        storage: &mut HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn fetch_balances(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let mut observations = Vec::new();
+        let height = Some(snapshot.height()?);
         for address in self.addresses.clone() {
             let path = Some("/cosmos.bank.v1beta1.Query/AllBalances".to_string());
             let req = cosmos_sdk_proto::cosmos::bank::v1beta1::QueryAllBalancesRequest {
@@ -84,31 +126,32 @@ impl CosmosBankAdapter {
                 resolve_denom: false,
             };
             let mut buf = Vec::with_capacity(req.encoded_len());
-            req.encode(&mut buf).unwrap();
+            req.encode(&mut buf)?;
 
-            let answer = self.client.abci_query(path, buf, None, false).await?;
-            let buf = answer.value;
+            let answer = self.client.abci_query(path, buf, height, false).await?;
+            // A failed query carries no balances, which would read as zero.
+            if answer.code.is_err() {
+                return Err(format!("balances of {} failed: {}", address, answer.log).into());
+            }
             let balance =
                 cosmos_sdk_proto::cosmos::bank::v1beta1::QueryAllBalancesResponse::decode(
-                    Bytes::from(buf),
-                )
-                .unwrap();
-            for b in balance.balances {
-                let denom = b.denom;
-                let amount = b.amount;
-                if self.denoms.contains(&denom) {
-                    let key = format!("balance_{}_{}", &address, denom);
-                    let val = storage.get(&self.get_key(&key));
-                    println!("{} - {:?}", self.get_key(&key), val);
-                    match val {
-                        Some(super::base::Value::Int(v)) => {
-                            v.set(amount.parse()?);
-                        }
-                        _ => unreachable!(),
-                    }
-                }
+                    Bytes::from(answer.value),
+                )?;
+            // Denoms the address does not hold are missing from the response.
+            for denom in self.denoms.iter() {
+                let amount = balance
+                    .balances
+                    .iter()
+                    .find(|b| &b.denom == denom)
+                    .map_or("0", |b| b.amount.as_str());
+                observations.push(Observation::new(
+                    Family::Balance,
+                    &[&address, denom, denom],
+                    Measurement::Amount(Amount::from_dec_str(amount, self.decimals)?),
+                    format!("balance_{}_{}", &address, denom),
+                ));
             }
         }
-        Ok(())
+        Ok(observations)
     }
 }
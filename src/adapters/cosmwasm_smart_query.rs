@@ -1,22 +1,106 @@
 use bytes::Bytes;
 use cosmos_sdk_proto::traits::Message;
+use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Arc;
+use tendermint::block::Height;
 use tendermint_rpc::Client;
-use tokio::sync::Mutex;
 
-use super::base::{MetricsAdapter, ValueType};
+use crate::chains::{Chains, CosmosClient, Network};
+use crate::check::check_cosmos;
+
+use super::base::{
+    AdapterConfig, EndpointConfig, Family, Measurement, MetricsAdapter, Observation, ValueType,
+    check_cosmos_address,
+};
+use super::jq::{compile_path, get_json_value_by_path};
+use super::snapshot::Snapshot;
 
 pub type StorageKey = String;
 pub type JqPath = String;
 pub type SmartQuery = String;
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct CosmWasmSmartQueryConfig {
+    pub contract: String,
+    pub objects: Vec<QueryConfig>,
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryConfig {
+    pub query: SmartQuery, // JSON message sent to the contract
+    pub keys: Vec<KeyConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyConfig {
+    pub key: StorageKey,
+    pub path: JqPath,
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for CosmWasmSmartQueryConfig {
+    const DEFAULT_CHAIN: &'static str = "cosmos";
+    const NETWORK: Network = Network::Cosmos;
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_cosmos_address(&mut problems, "contract", &self.contract);
+        for (i, object) in self.objects.iter().enumerate() {
+            if serde_json::from_str::<Value>(&object.query).is_err() {
+                problems.push(format!("objects[{}].query: invalid JSON", i));
+            }
+            for (j, key) in object.keys.iter().enumerate() {
+                if compile_path(&key.path).is_none() {
+                    problems.push(format!("objects[{}].keys[{}].path: invalid jq path", i, j));
+                }
+            }
+        }
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        let queries = self
+            .objects
+            .iter()
+            .map(|object| {
+                let keys = object
+                    .keys
+                    .iter()
+                    .map(|key| (key.key.clone(), key.path.clone(), key.value_type.clone()))
+                    .collect::<Vec<_>>();
+                (object.query.clone(), keys)
+            })
+            .collect::<HashMap<_, _>>();
+        let client = chains.cosmos(self.endpoint.as_endpoint())?;
+        Ok(Box::new(
+            CosmWasmSmartQueryAdapter::new(name, &self.contract, queries, client).await?,
+        ))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let client = chains.cosmos(self.endpoint.as_endpoint())?;
+        check_cosmos(&client, Some(&self.contract)).await
+    }
+}
+
 pub struct CosmWasmSmartQueryAdapter {
     name: String,
     contract_address: String,
     queries: HashMap<SmartQuery, Vec<(StorageKey, JqPath, ValueType)>>,
-    client: tendermint_rpc::HttpClient,
+    client: CosmosClient,
 }
 
 #[async_trait::async_trait]
@@ -25,92 +109,66 @@ impl MetricsAdapter for CosmWasmSmartQueryAdapter {
         &self.name
     }
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let storage = metrics.lock().await;
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::cosmos(&self.client).await?;
+        let mut observations = snapshot.observations();
+        let height = Some(snapshot.height()?);
         for (smart_query, items) in self.queries.iter() {
-            let path = Some("/cosmwasm.wasm.v1.Query/SmartContractState".to_string());
-            let req = cosmos_sdk_proto::cosmwasm::wasm::v1::QuerySmartContractStateRequest {
-                address: self.contract_address.to_string(),
-                query_data: smart_query.to_string().into(),
-            };
-            let mut buf = Vec::with_capacity(req.encoded_len());
-            req.encode(&mut buf).unwrap();
-            let answer = self.client.abci_query(path, buf, None, false).await?;
-            let buf = answer.value;
-            let response =
-                cosmos_sdk_proto::cosmwasm::wasm::v1::QuerySmartContractStateResponse::decode(
-                    Bytes::from(buf),
-                )
-                .unwrap();
-            let json: Value = serde_json::from_slice(&response.data)?;
+            let json =
+                query_contract(&self.client, &self.contract_address, smart_query, height).await?;
             for (key, jq_path, value_type) in items {
-                let new_value: Value =
-                    get_json_value_by_path(json.clone().into(), jq_path).unwrap();
-                match value_type {
-                    ValueType::Int => {
-                        let key = self.get_key(&key);
-                        let val = storage.get(&key);
-                        match val {
-                            Some(super::base::Value::Int(v)) => {
-                                v.set(new_value.as_i64().unwrap());
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    ValueType::Float => {
-                        let key = self.get_key(&key);
-                        let val = storage.get(&key);
-                        match val {
-                            Some(super::base::Value::Float(v)) => {
-                                v.set(new_value.as_f64().unwrap());
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
+                let new_value: Value = get_json_value_by_path(json.clone().into(), jq_path)
+                    .map_err(|e| format!("{}: {}", key, e))?
+                    .ok_or_else(|| format!("{}: no value at {}", key, jq_path))?;
+                let value = match value_type {
+                    ValueType::Int => new_value.as_i64().map(Measurement::Int),
+                    ValueType::Float => new_value.as_f64().map(Measurement::Float),
                 }
+                .ok_or_else(|| format!("{}: {} is not a {:?} value", key, new_value, value_type))?;
+                observations.push(Observation::new(
+                    Family::ContractValue,
+                    &[&self.contract_address, key],
+                    value,
+                    key.clone(),
+                ));
             }
         }
-        Ok(())
+        Ok(observations)
     }
 }
 
+/// Sends a smart query to a CosmWasm contract at `height` and parses the
+/// JSON it answers with.
+pub async fn query_contract(
+    client: &CosmosClient,
+    contract: &str,
+    query: &str,
+    height: Option<Height>,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let path = Some("/cosmwasm.wasm.v1.Query/SmartContractState".to_string());
+    let req = cosmos_sdk_proto::cosmwasm::wasm::v1::QuerySmartContractStateRequest {
+        address: contract.to_string(),
+        query_data: query.to_string().into(),
+    };
+    let mut buf = Vec::with_capacity(req.encoded_len());
+    req.encode(&mut buf)?;
+    let answer = client.abci_query(path, buf, height, false).await?;
+    if answer.code.is_err() {
+        return Err(format!("query to {} failed: {}", contract, answer.log).into());
+    }
+    let response = cosmos_sdk_proto::cosmwasm::wasm::v1::QuerySmartContractStateResponse::decode(
+        Bytes::from(answer.value),
+    )?;
+    Ok(serde_json::from_slice(&response.data)?)
+}
+
 impl CosmWasmSmartQueryAdapter {
     pub async fn new(
         name: &str,
         contract_address: &str,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
         queries: HashMap<SmartQuery, Vec<(StorageKey, JqPath, ValueType)>>,
-        rpc: &str,
+        client: CosmosClient,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let client = tendermint_rpc::HttpClient::builder(rpc.parse()?).build()?;
-
-        let mut storage = metrics.lock().await;
-
-        for (_, rows) in queries.iter() {
-            for (key, _, value_type) in rows.iter() {
-                let key = format!("{}_{}", name, key);
-                match *value_type {
-                    ValueType::Int => {
-                        let value = super::base::Value::Int(prometheus::IntGauge::new(
-                            key.clone(),
-                            format!("Value of {}", key),
-                        )?);
-                        storage.insert(key.to_string(), value);
-                    }
-                    ValueType::Float => {
-                        let value = super::base::Value::Float(prometheus::Gauge::new(
-                            key.clone(),
-                            format!("Value of {}", key),
-                        )?);
-                        storage.insert(key.to_string(), value);
-                    }
-                }
-            }
-        }
-
         Ok(Self {
             name: name.to_string(),
             contract_address: contract_address.to_string(),
@@ -119,29 +177,3 @@ impl CosmWasmSmartQueryAdapter {
         })
     }
 }
-
-fn get_json_value_by_path(json: jaq_json::Val, path: &str) -> Option<Value> {
-    use jaq_core::load::{Arena, File, Loader};
-
-    let arena = Arena::default();
-    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
-    let modules = loader
-        .load(&arena, File {
-            path: (),
-            code: path,
-        })
-        .unwrap();
-    let filter = jaq_core::Compiler::default()
-        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
-        .compile(modules)
-        .unwrap();
-    let inputs = jaq_core::RcIter::new(core::iter::empty());
-    let out = filter
-        .run((jaq_core::Ctx::new([], &inputs), json))
-        .collect::<Vec<_>>();
-    if out.is_empty() {
-        None
-    } else {
-        Some(out[0].clone().unwrap().into())
-    }
-}
@@ -1,18 +1,268 @@
-use std::collections::HashMap;
+use crate::chains::{Chains, EvmProvider};
+use crate::check::check_evm_contracts;
+use crate::config::ChainId;
+use std::collections::HashSet;
 use std::sync::Arc;
 
-use super::base::MetricsAdapter;
+use super::base::{
+    AdapterConfig, Amount, EndpointConfig, ExportUnit, Family, Measurement, MetricsAdapter,
+    Observation, check_evm_address, check_evm_addresses, strs,
+};
+use super::multicall::{BatchReader, Read};
+use super::snapshot::Snapshot;
+use ethers::providers::Middleware;
 use ethers::{abi::Abi, types::U256};
-use tokio::sync::Mutex;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Erc20Config {
+    pub addresses: Vec<String>,
+    pub contract: Option<String>,
+    #[serde(default)]
+    pub contracts: Vec<String>, // more tokens held by `addresses`
+    pub token_list: Option<String>, // path of a Uniswap token list, filtered by chain id
+    pub decimals: Option<u8>,       // overrides `decimals()` of `contract`
+    pub symbol: Option<String>,     // overrides `symbol()` of `contract`
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+/// A token list in the format of https://tokenlists.org, of which only the
+/// fields needed here are read.
+#[derive(Debug, Deserialize)]
+struct TokenList {
+    tokens: Vec<ListedToken>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListedToken {
+    chain_id: u64,
+    address: String,
+    symbol: String,
+    decimals: u8,
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for Erc20Config {
+    // Cents, from scaling by `decimals - 2`.
+    const LEGACY_UNIT: ExportUnit = ExportUnit::Fixed(2);
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_evm_addresses(&mut problems, "addresses", &self.addresses);
+        if let Some(contract) = &self.contract {
+            check_evm_address(&mut problems, "contract", contract);
+        }
+        for (i, contract) in self.contracts.iter().enumerate() {
+            check_evm_address(&mut problems, &format!("contracts[{}]", i), contract);
+        }
+        if let Some(path) = &self.token_list
+            && !std::path::Path::new(path).is_file()
+        {
+            problems.push(format!("token_list: no file at {}", path));
+        }
+        if self.contract.is_none() && self.contracts.is_empty() && self.token_list.is_none() {
+            problems.push("contract: missing, set contract, contracts or token_list".to_string());
+        }
+        if self.contract.is_none() && self.decimals.is_some() {
+            problems.push("decimals: only applies to `contract`".to_string());
+        }
+        if self.contract.is_none() && self.symbol.is_some() {
+            problems.push("symbol: only applies to `contract`".to_string());
+        }
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        let client = chains.evm(self.endpoint.as_endpoint())?;
+        let mut tokens = Vec::new();
+        if let Some(contract) = &self.contract {
+            let symbol = self.symbol.as_deref();
+            tokens.push(Token::detect(name, contract, &client, self.decimals, symbol).await?);
+        }
+        for contract in &self.contracts {
+            tokens.push(Token::detect(name, contract, &client, None, None).await?);
+        }
+        if let Some(path) = &self.token_list {
+            let chain_id = match self
+                .endpoint
+                .chain
+                .as_deref()
+                .and_then(|chain| chains.get(chain))
+            {
+                Some(chain) if let Some(ChainId::Evm(id)) = chain.chain_id => id,
+                _ => client.get_chainid().await?.as_u64(),
+            };
+            for token in read_token_list(path, chain_id)? {
+                tokens.push(Token::listed(
+                    &client,
+                    &token.address,
+                    token.symbol,
+                    token.decimals,
+                )?);
+            }
+        }
+
+        // A token named twice, e.g. in `contracts` and the token list, is read once.
+        let mut seen = HashSet::new();
+        tokens.retain(|token| seen.insert(token.asset.to_lowercase()));
+
+        Ok(Box::new(Erc20Adapter::new(
+            name,
+            strs(&self.addresses),
+            tokens,
+            client,
+            chains.batch_size(self.endpoint.chain.as_deref()),
+        )?))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let client = chains.evm(self.endpoint.as_endpoint())?;
+        let contracts = self.contract.iter().chain(&self.contracts);
+        check_evm_contracts(client, contracts.map(String::as_str)).await
+    }
+}
+
+/// The tokens of a token list that live on `chain_id`.
+fn read_token_list(
+    path: &str,
+    chain_id: u64,
+) -> Result<Vec<ListedToken>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read token list {}: {}", path, e))?;
+    let list: TokenList = serde_json::from_str(&content)
+        .map_err(|e| format!("invalid token list {}: {}", path, e))?;
+    let tokens = list
+        .tokens
+        .into_iter()
+        .filter(|token| token.chain_id == chain_id)
+        .collect::<Vec<_>>();
+    if tokens.is_empty() {
+        return Err(format!("token list {} has no tokens on chain {}", path, chain_id).into());
+    }
+    Ok(tokens)
+}
+
+type Erc20Contract = ethers::contract::ContractInstance<Arc<EvmProvider>, EvmProvider>;
+
+fn erc20_contract(
+    client: &Arc<EvmProvider>,
+    contract: &str,
+) -> Result<Erc20Contract, Box<dyn std::error::Error>> {
+    let abi: Abi = serde_json::from_str(
+        r#"[{"constant":true,"inputs":[],"name":"name","outputs":[{"name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_upgradedAddress","type":"address"}],"name":"deprecate","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"_spender","type":"address"},{"name":"_value","type":"uint256"}],"name":"approve","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"deprecated","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_evilUser","type":"address"}],"name":"addBlackList","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"totalSupply","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_from","type":"address"},{"name":"_to","type":"address"},{"name":"_value","type":"uint256"}],"name":"transferFrom","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"upgradedAddress","outputs":[{"name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"","type":"address"}],"name":"balances","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"decimals","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"maximumFee","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"_totalSupply","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[],"name":"unpause","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"name":"_maker","type":"address"}],"name":"getBlackListStatus","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"","type":"address"},{"name":"","type":"address"}],"name":"allowed","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"paused","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"who","type":"address"}],"name":"balanceOf","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[],"name":"pause","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"getOwner","outputs":[{"name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"owner","outputs":[{"name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"symbol","outputs":[{"name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_to","type":"address"},{"name":"_value","type":"uint256"}],"name":"transfer","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"newBasisPoints","type":"uint256"},{"name":"newMaxFee","type":"uint256"}],"name":"setParams","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"amount","type":"uint256"}],"name":"issue","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"amount","type":"uint256"}],"name":"redeem","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"name":"_owner","type":"address"},{"name":"_spender","type":"address"}],"name":"allowance","outputs":[{"name":"remaining","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"basisPointsRate","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"","type":"address"}],"name":"isBlackListed","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_clearedUser","type":"address"}],"name":"removeBlackList","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"MAX_UINT","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"newOwner","type":"address"}],"name":"transferOwnership","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"_blackListedUser","type":"address"}],"name":"destroyBlackFunds","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"inputs":[{"name":"_initialSupply","type":"uint256"},{"name":"_name","type":"string"},{"name":"_symbol","type":"string"},{"name":"_decimals","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"constructor"},{"anonymous":false,"inputs":[{"indexed":false,"name":"amount","type":"uint256"}],"name":"Issue","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"amount","type":"uint256"}],"name":"Redeem","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"newAddress","type":"address"}],"name":"Deprecate","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"feeBasisPoints","type":"uint256"},{"indexed":false,"name":"maxFee","type":"uint256"}],"name":"Params","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"_blackListedUser","type":"address"},{"indexed":false,"name":"_balance","type":"uint256"}],"name":"DestroyedBlackFunds","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"_user","type":"address"}],"name":"AddedBlackList","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"_user","type":"address"}],"name":"RemovedBlackList","type":"event"},{"anonymous":false,"inputs":[{"indexed":true,"name":"owner","type":"address"},{"indexed":true,"name":"spender","type":"address"},{"indexed":false,"name":"value","type":"uint256"}],"name":"Approval","type":"event"},{"anonymous":false,"inputs":[{"indexed":true,"name":"from","type":"address"},{"indexed":true,"name":"to","type":"address"},{"indexed":false,"name":"value","type":"uint256"}],"name":"Transfer","type":"event"},{"anonymous":false,"inputs":[],"name":"Pause","type":"event"},{"anonymous":false,"inputs":[],"name":"Unpause","type":"event"}]"#,
+    )?;
+    let token_address: ethers::types::Address = contract.parse()?;
+    Ok(ethers::contract::Contract::new(
+        token_address,
+        abi,
+        Arc::clone(client),
+    ))
+}
+
+/// One token contract and how its balances are scaled and labelled.
+pub struct Token {
+    asset: String,
+    symbol: String,
+    decimals: u8,
+    contract: Erc20Contract,
+}
+
+impl Token {
+    /// Reads `decimals()` and `symbol()` from the contract, unless overridden.
+    pub async fn detect(
+        name: &str,
+        contract: &str,
+        client: &Arc<EvmProvider>,
+        decimals: Option<u8>,
+        symbol: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let token = erc20_contract(client, contract)?;
+
+        // Configured values win, but a disagreement usually means a typo that
+        // would mis-scale every balance, so it is reported.
+        let detected = token.method::<_, U256>("decimals", ())?.call().await;
+        let decimals = match (decimals, detected) {
+            (Some(configured), Ok(detected)) => {
+                if U256::from(configured) != detected {
+                    eprintln!(
+                        "Warning: {}: configured decimals {} differ from decimals() = {} of {}, using {}",
+                        name, configured, detected, contract, configured
+                    );
+                }
+                configured
+            }
+            (Some(configured), Err(_)) => configured,
+            (None, Ok(detected)) => u8::try_from(detected)
+                .map_err(|_| format!("decimals() of {} returned {}", contract, detected))?,
+            (None, Err(e)) => {
+                return Err(format!(
+                    "cannot read decimals() of {}, set decimals in the config: {}",
+                    contract, e
+                )
+                .into());
+            }
+        };
+        let detected = token.method::<_, String>("symbol", ())?.call().await;
+        let symbol = match (symbol, detected) {
+            (Some(configured), Ok(detected)) => {
+                if configured != detected {
+                    eprintln!(
+                        "Warning: {}: configured symbol {} differs from symbol() = {} of {}, using {}",
+                        name, configured, detected, contract, configured
+                    );
+                }
+                configured.to_string()
+            }
+            (Some(configured), Err(_)) => configured.to_string(),
+            (None, Ok(detected)) => detected,
+            (None, Err(e)) => {
+                eprintln!(
+                    "Warning: {}: cannot read symbol() of {}, leaving the symbol label empty: {}",
+                    name, contract, e
+                );
+                String::new()
+            }
+        };
+
+        Ok(Self {
+            asset: contract.to_string(),
+            symbol,
+            decimals,
+            contract: token,
+        })
+    }
+
+    /// A token as described by a token list, trusted without reading the contract.
+    pub fn listed(
+        client: &Arc<EvmProvider>,
+        contract: &str,
+        symbol: String,
+        decimals: u8,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            asset: contract.to_string(),
+            symbol,
+            decimals,
+            contract: erc20_contract(client, contract)?,
+        })
+    }
+}
 
 pub struct Erc20Adapter {
     name: String,
-    decimals: u8,
     addresses: Vec<String>,
-    contract: ethers::contract::ContractInstance<
-        Arc<ethers::providers::Provider<ethers::providers::Http>>,
-        ethers::providers::Provider<ethers::providers::Http>,
-    >,
+    tokens: Vec<Token>,
+    reader: BatchReader,
 }
 
 #[async_trait::async_trait]
@@ -21,77 +271,72 @@ impl MetricsAdapter for Erc20Adapter {
         &self.name
     }
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut storage = metrics.lock().await;
-        self.update_balances(&mut storage).await?;
-        Ok(())
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::evm(self.reader.client()).await?;
+        let mut observations = self.fetch_balances(&snapshot).await?;
+        observations.extend(snapshot.observations());
+        Ok(observations)
     }
 }
 
 impl Erc20Adapter {
-    pub async fn new(
+    pub fn new(
         name: &str,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
         addresses: Vec<&str>,
-        contract: &str,
-        rpc: &str,
-        decimals: u8,
+        tokens: Vec<Token>,
+        client: Arc<EvmProvider>,
+        batch_size: usize,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let abi: Abi = serde_json::from_str(
-            r#"[{"constant":true,"inputs":[],"name":"name","outputs":[{"name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_upgradedAddress","type":"address"}],"name":"deprecate","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"_spender","type":"address"},{"name":"_value","type":"uint256"}],"name":"approve","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"deprecated","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_evilUser","type":"address"}],"name":"addBlackList","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"totalSupply","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_from","type":"address"},{"name":"_to","type":"address"},{"name":"_value","type":"uint256"}],"name":"transferFrom","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"upgradedAddress","outputs":[{"name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"","type":"address"}],"name":"balances","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"decimals","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"maximumFee","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"_totalSupply","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[],"name":"unpause","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"name":"_maker","type":"address"}],"name":"getBlackListStatus","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"","type":"address"},{"name":"","type":"address"}],"name":"allowed","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"paused","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"who","type":"address"}],"name":"balanceOf","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[],"name":"pause","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"getOwner","outputs":[{"name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"owner","outputs":[{"name":"","type":"address"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"symbol","outputs":[{"name":"","type":"string"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_to","type":"address"},{"name":"_value","type":"uint256"}],"name":"transfer","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"newBasisPoints","type":"uint256"},{"name":"newMaxFee","type":"uint256"}],"name":"setParams","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"amount","type":"uint256"}],"name":"issue","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"amount","type":"uint256"}],"name":"redeem","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[{"name":"_owner","type":"address"},{"name":"_spender","type":"address"}],"name":"allowance","outputs":[{"name":"remaining","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[],"name":"basisPointsRate","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":true,"inputs":[{"name":"","type":"address"}],"name":"isBlackListed","outputs":[{"name":"","type":"bool"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"_clearedUser","type":"address"}],"name":"removeBlackList","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":true,"inputs":[],"name":"MAX_UINT","outputs":[{"name":"","type":"uint256"}],"payable":false,"stateMutability":"view","type":"function"},{"constant":false,"inputs":[{"name":"newOwner","type":"address"}],"name":"transferOwnership","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"constant":false,"inputs":[{"name":"_blackListedUser","type":"address"}],"name":"destroyBlackFunds","outputs":[],"payable":false,"stateMutability":"nonpayable","type":"function"},{"inputs":[{"name":"_initialSupply","type":"uint256"},{"name":"_name","type":"string"},{"name":"_symbol","type":"string"},{"name":"_decimals","type":"uint256"}],"payable":false,"stateMutability":"nonpayable","type":"constructor"},{"anonymous":false,"inputs":[{"indexed":false,"name":"amount","type":"uint256"}],"name":"Issue","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"amount","type":"uint256"}],"name":"Redeem","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"newAddress","type":"address"}],"name":"Deprecate","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"feeBasisPoints","type":"uint256"},{"indexed":false,"name":"maxFee","type":"uint256"}],"name":"Params","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"_blackListedUser","type":"address"},{"indexed":false,"name":"_balance","type":"uint256"}],"name":"DestroyedBlackFunds","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"_user","type":"address"}],"name":"AddedBlackList","type":"event"},{"anonymous":false,"inputs":[{"indexed":false,"name":"_user","type":"address"}],"name":"RemovedBlackList","type":"event"},{"anonymous":false,"inputs":[{"indexed":true,"name":"owner","type":"address"},{"indexed":true,"name":"spender","type":"address"},{"indexed":false,"name":"value","type":"uint256"}],"name":"Approval","type":"event"},{"anonymous":false,"inputs":[{"indexed":true,"name":"from","type":"address"},{"indexed":true,"name":"to","type":"address"},{"indexed":false,"name":"value","type":"uint256"}],"name":"Transfer","type":"event"},{"anonymous":false,"inputs":[],"name":"Pause","type":"event"},{"anonymous":false,"inputs":[],"name":"Unpause","type":"event"}]"#,
-        )?;
-        let provider = ethers::prelude::Provider::<ethers::providers::Http>::try_from(rpc)?;
-        let client = Arc::new(provider);
-        let token_address: ethers::types::Address = contract.parse()?;
-
-        let contract = ethers::contract::Contract::new(token_address, abi, client.clone());
-
-        let mut storage = metrics.lock().await;
-        for addr in addresses.clone() {
-            let key = format!("{}_balance_{}", name, addr);
-            let value = super::base::Value::Int(prometheus::IntGauge::new(
-                &key,
-                format!("Value of {}", key),
-            )?);
-            storage.insert(key, value);
-        }
-
+        let addresses = addresses
+            .iter()
+            .map(|addr| {
+                addr.parse::<ethers::types::Address>()
+                    .map(|_| addr.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         Ok(Self {
             name: name.to_string(),
-            decimals,
-            addresses: addresses
-                .clone()
-                .iter()
-                .map(|addr| addr.parse().unwrap())
-                .collect(),
-            contract,
+            addresses,
+            tokens,
+            reader: BatchReader::new(client, batch_size),
         })
     }
 
-    async fn update_balances(
-        &mut self,
-        storage: &mut HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        for addr in self.addresses.clone() {
-            let address: ethers::types::Address = addr.parse()?;
-            let balance: U256 = self
-                .contract
-                .method::<_, U256>("balanceOf", address)?
-                .call()
-                .await?
-                .checked_div(U256::from(10).pow(self.decimals.into()))
-                .unwrap();
-            let key = "balance_".to_string() + &addr;
-            match storage.get(&self.get_key(&key)) {
-                Some(super::base::Value::Int(v)) => {
-                    v.set(balance.as_u64() as i64);
-                }
-                _ => unreachable!(),
+    /// Reads every token × holder balance in one batch.
+    async fn fetch_balances(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let mut reads = Vec::new();
+        for token in &self.tokens {
+            for addr in self.addresses.iter() {
+                let address: ethers::types::Address = addr.parse()?;
+                let call = token.contract.method::<_, U256>("balanceOf", address)?;
+                reads.push(Read::Call(Box::new(call)));
+            }
+        }
+        let mut balances = self
+            .reader
+            .read(reads, snapshot.height.into())
+            .await?
+            .into_iter();
+
+        let mut observations = Vec::new();
+        for token in &self.tokens {
+            for (addr, balance) in self.addresses.iter().zip(balances.by_ref()) {
+                // A single token keeps the keys it had before tokens could be listed.
+                let key = match self.tokens.len() {
+                    1 => format!("balance_{}", addr),
+                    _ => format!("balance_{}_{}", addr, token.asset),
+                };
+                observations.push(Observation::new(
+                    Family::Balance,
+                    &[addr, &token.asset, &token.symbol],
+                    Measurement::Amount(Amount::new(balance, token.decimals)),
+                    key,
+                ));
             }
         }
-        Ok(())
+        Ok(observations)
     }
 }
@@ -1,16 +1,79 @@
-use std::collections::HashMap;
+use crate::chains::{Chains, EvmProvider};
+use crate::check::check_evm;
 use std::sync::Arc;
 
-use super::base::MetricsAdapter;
-use ethers::providers::Middleware;
-use ethers::types::U256;
-use tokio::sync::Mutex;
+use super::base::{
+    AdapterConfig, Amount, EndpointConfig, ExportUnit, Family, Measurement, MetricsAdapter,
+    Observation, check_evm_addresses, strs,
+};
+use super::multicall::{BatchReader, Read};
+use super::snapshot::Snapshot;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EthConfig {
+    pub addresses: Vec<String>,
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for EthConfig {
+    // Cents, from scaling by `decimals - 2`.
+    const LEGACY_UNIT: ExportUnit = ExportUnit::Fixed(2);
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_evm_addresses(&mut problems, "addresses", &self.addresses);
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        let symbol = self
+            .endpoint
+            .chain
+            .as_deref()
+            .and_then(|chain| chains.get(chain))
+            .and_then(|chain| chain.native_symbol.as_deref())
+            .unwrap_or("ETH");
+        Ok(Box::new(
+            ETHAdapter::new(
+                name,
+                strs(&self.addresses),
+                chains.evm(self.endpoint.as_endpoint())?,
+                self.decimals,
+                symbol,
+                chains.batch_size(self.endpoint.chain.as_deref()),
+            )
+            .await?,
+        ))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        check_evm(chains.evm(self.endpoint.as_endpoint())?, None).await
+    }
+}
 
 pub struct ETHAdapter {
     name: String,
     decimals: u8,
+    symbol: String,
     addresses: Vec<String>,
-    client: Arc<ethers::prelude::Provider<ethers::providers::Http>>,
+    reader: BatchReader,
 }
 
 #[async_trait::async_trait]
@@ -19,40 +82,28 @@ impl MetricsAdapter for ETHAdapter {
         &self.name
     }
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut storage = metrics.lock().await;
-        self.update_balances(&mut storage).await?;
-        Ok(())
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::evm(self.reader.client()).await?;
+        let mut observations = self.fetch_balances(&snapshot).await?;
+        observations.extend(snapshot.observations());
+        Ok(observations)
     }
 }
 
 impl ETHAdapter {
     pub async fn new(
         name: &str,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
         addresses: Vec<&str>,
-        rpc: &str,
+        client: Arc<EvmProvider>,
         decimals: u8,
+        symbol: &str,
+        batch_size: usize,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let provider = ethers::prelude::Provider::<ethers::providers::Http>::try_from(rpc)?;
-        let client = Arc::new(provider);
-        let mut storage = metrics.lock().await;
-        for addr in addresses.clone() {
-            let key = format!("{}_balance_{}", name, addr);
-            let value = super::base::Value::Int(prometheus::IntGauge::new(
-                &key,
-                format!("Value of {}", key),
-            )?);
-            storage.insert(key, value);
-        }
-
         Ok(Self {
             name: name.to_string(),
             decimals,
-            client,
+            symbol: symbol.to_string(),
+            reader: BatchReader::new(client, batch_size),
             addresses: addresses
                 .clone()
                 .iter()
@@ -61,26 +112,28 @@ impl ETHAdapter {
         })
     }
 
-    async fn update_balances(
-        &mut self,
-        storage: &mut HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        for addr in self.addresses.clone() {
-            let address: ethers::types::Address = addr.parse()?;
-            let balance: U256 = self
-                .client
-                .get_balance(address, None)
-                .await?
-                .checked_div(U256::from(10).pow(self.decimals.into()))
-                .unwrap();
-            let key = "balance_".to_string() + &addr;
-            match storage.get(&self.get_key(&key)) {
-                Some(super::base::Value::Int(v)) => {
-                    v.set(balance.as_u64() as i64);
-                }
-                _ => unreachable!(),
-            }
+    async fn fetch_balances(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let mut reads = Vec::new();
+        for addr in self.addresses.iter() {
+            reads.push(Read::Balance(addr.parse()?));
         }
-        Ok(())
+        let balances = self.reader.read(reads, snapshot.height.into()).await?;
+
+        Ok(self
+            .addresses
+            .iter()
+            .zip(balances)
+            .map(|(addr, balance)| {
+                Observation::new(
+                    Family::Balance,
+                    &[addr, &self.symbol, &self.symbol],
+                    Measurement::Amount(Amount::new(balance, self.decimals)),
+                    format!("balance_{}", addr),
+                )
+            })
+            .collect())
     }
 }
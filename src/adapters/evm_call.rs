@@ -0,0 +1,345 @@
+use crate::chains::{Chains, EvmProvider};
+use crate::check::check_evm;
+use std::sync::Arc;
+
+use super::base::{
+    AdapterConfig, Amount, EndpointConfig, Family, Measurement, MetricsAdapter, Observation,
+    ValueType, check_evm_address,
+};
+use super::jq::{compile_path, get_json_value_by_path};
+use super::snapshot::Snapshot;
+use ethers::abi::token::{LenientTokenizer, Tokenizer};
+use ethers::abi::{Function, HumanReadableParser, Token};
+use ethers::providers::Middleware;
+use ethers::types::{Address, Bytes, TransactionRequest};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvmCallConfig {
+    pub contract: String,
+    pub calls: Vec<CallConfig>,
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallConfig {
+    pub signature: String, // e.g. `getReserves()(uint112,uint112,uint32)`
+    #[serde(default)]
+    pub args: Vec<Value>,
+    pub keys: Vec<CallKeyConfig>,
+}
+
+/// One value taken from a call's outputs, which jq sees as an array.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallKeyConfig {
+    pub key: String,
+    pub path: String,
+    #[serde(rename = "type")]
+    pub value_type: Option<ValueType>,
+    pub decimals: Option<u8>, // exports an integer output as a token amount
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for EvmCallConfig {
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_evm_address(&mut problems, "contract", &self.contract);
+        for (i, call) in self.calls.iter().enumerate() {
+            if let Err(e) = call.encode() {
+                problems.push(format!("calls[{}].{}", i, e));
+            }
+            for (j, key) in call.keys.iter().enumerate() {
+                if compile_path(&key.path).is_none() {
+                    problems.push(format!("calls[{}].keys[{}].path: invalid jq path", i, j));
+                }
+                if key.value_type.is_some() == key.decimals.is_some() {
+                    problems.push(format!(
+                        "calls[{}].keys[{}]: set either type or decimals",
+                        i, j
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        let calls = self
+            .calls
+            .iter()
+            .map(|call| {
+                let (function, data) = call.encode()?;
+                Ok(Call {
+                    function,
+                    data,
+                    keys: call.keys.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Box::new(EvmCallAdapter::new(
+            name,
+            &self.contract,
+            calls,
+            chains.evm(self.endpoint.as_endpoint())?,
+        )?))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let client = chains.evm(self.endpoint.as_endpoint())?;
+        check_evm(client, Some(&self.contract)).await
+    }
+}
+
+impl CallConfig {
+    /// Parses the signature and encodes the arguments, failing with a
+    /// `field: message` problem.
+    fn encode(&self) -> Result<(Function, Bytes), String> {
+        let function = HumanReadableParser::parse_function(&self.signature)
+            .map_err(|e| format!("signature: {}", e))?;
+        if self.args.len() != function.inputs.len() {
+            return Err(format!(
+                "args: {} takes {} arguments, got {}",
+                function.name,
+                function.inputs.len(),
+                self.args.len()
+            ));
+        }
+        let tokens = function
+            .inputs
+            .iter()
+            .zip(&self.args)
+            .enumerate()
+            .map(|(i, (input, arg))| {
+                let arg = match arg {
+                    Value::String(arg) => arg.clone(),
+                    arg => arg.to_string(),
+                };
+                LenientTokenizer::tokenize(&input.kind, &arg)
+                    .map_err(|e| format!("args[{}]: not a {}: {}", i, input.kind, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let data = function
+            .encode_input(&tokens)
+            .map_err(|e| format!("args: {}", e))?;
+        Ok((function, data.into()))
+    }
+}
+
+pub struct Call {
+    function: Function,
+    data: Bytes,
+    keys: Vec<CallKeyConfig>,
+}
+
+pub struct EvmCallAdapter {
+    name: String,
+    contract: String,
+    address: Address,
+    calls: Vec<Call>,
+    client: Arc<EvmProvider>,
+}
+
+#[async_trait::async_trait]
+impl MetricsAdapter for EvmCallAdapter {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::evm(&self.client).await?;
+        let mut observations = snapshot.observations();
+        for call in &self.calls {
+            let tx = TransactionRequest::new()
+                .to(self.address)
+                .data(call.data.clone());
+            let output = self.client.call(&tx.into(), Some(snapshot.block())).await?;
+            let tokens = call.function.decode_output(&output)?;
+            let json = Value::Array(tokens.into_iter().map(token_json).collect());
+            for key in &call.keys {
+                let value = get_json_value_by_path(json.clone().into(), &key.path)
+                    .map_err(|e| format!("{}: {}", key.key, e))?
+                    .ok_or_else(|| format!("{}: no value at {}", key.key, key.path))?;
+                observations.push(Observation::new(
+                    Family::ContractValue,
+                    &[&self.contract, &key.key],
+                    measurement(key, &value)?,
+                    key.key.clone(),
+                ));
+            }
+        }
+        Ok(observations)
+    }
+}
+
+impl EvmCallAdapter {
+    pub fn new(
+        name: &str,
+        contract: &str,
+        calls: Vec<Call>,
+        client: Arc<EvmProvider>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            name: name.to_string(),
+            contract: contract.to_string(),
+            address: contract.parse()?,
+            calls,
+            client,
+        })
+    }
+}
+
+/// A decoded output as JSON. Integers become decimal strings, since they
+/// rarely fit into a JSON number, and addresses and bytes become hex strings.
+fn token_json(token: Token) -> Value {
+    match token {
+        Token::Uint(value) => Value::String(value.to_string()),
+        Token::Int(value) => Value::String(ethers::types::I256::from_raw(value).to_string()),
+        Token::Address(address) => Value::String(format!("{:?}", address)),
+        Token::Bool(value) => Value::Bool(value),
+        Token::String(value) => Value::String(value),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => {
+            Value::String(Bytes::from(bytes).to_string())
+        }
+        Token::Array(tokens) | Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+            Value::Array(tokens.into_iter().map(token_json).collect())
+        }
+    }
+}
+
+/// Converts a value picked by jq as its key says. Integers may arrive as
+/// decimal strings.
+fn measurement(key: &CallKeyConfig, value: &Value) -> Result<Measurement, String> {
+    let number = match value {
+        Value::String(number) => Some(number.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(value) => Some((*value as u8).to_string()),
+        _ => None,
+    };
+    let measurement = match (key.decimals, &key.value_type, number) {
+        (Some(decimals), _, Some(number)) => Amount::from_dec_str(&number, decimals)
+            .ok()
+            .map(Measurement::Amount),
+        (None, Some(ValueType::Int), Some(number)) => number.parse().ok().map(Measurement::Int),
+        (None, Some(ValueType::Float), Some(number)) => number.parse().ok().map(Measurement::Float),
+        _ => None,
+    };
+    measurement.ok_or_else(|| format!("{}: cannot convert {}", key.key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::encode;
+    use ethers::types::{I256, U256};
+    use serde_json::json;
+
+    /// Decodes `tokens` as the outputs of `signature` and converts them the
+    /// way `collect` does.
+    fn outputs(signature: &str, tokens: &[Token]) -> Value {
+        let call = CallConfig {
+            signature: signature.to_string(),
+            args: Vec::new(),
+            keys: Vec::new(),
+        };
+        let (function, _) = call.encode().unwrap();
+        let decoded = function.decode_output(&encode(tokens)).unwrap();
+        Value::Array(decoded.into_iter().map(token_json).collect())
+    }
+
+    fn int(value: i64) -> Token {
+        Token::Int(I256::from(value).into_raw())
+    }
+
+    #[test]
+    fn token_json_keeps_uint256_beyond_u64() {
+        assert_eq!(
+            outputs(
+                "f()(uint256,uint64)",
+                &[Token::Uint(U256::MAX), Token::Uint(U256::from(7))]
+            ),
+            json!([
+                "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+                "7"
+            ])
+        );
+    }
+
+    #[test]
+    fn token_json_signs_ints() {
+        assert_eq!(
+            outputs(
+                "f()(int256,int128,int8)",
+                &[Token::Int(I256::MIN.into_raw()), int(-5), int(127)]
+            ),
+            json!([
+                "-57896044618658097711785492504343953926634992332820282019728792003956564819968",
+                "-5",
+                "127"
+            ])
+        );
+    }
+
+    #[test]
+    fn token_json_nests_tuples_and_arrays() {
+        let address = Address::from_low_u64_be(0xabc);
+        let tokens = [
+            Token::Tuple(vec![
+                Token::Address(address),
+                Token::Bool(true),
+                Token::Array(vec![int(-1), int(2)]),
+            ]),
+            Token::FixedArray(vec![Token::Uint(U256::from(1)), Token::Uint(U256::from(2))]),
+            Token::Bytes(vec![0xde, 0xad]),
+            Token::String("USDC".to_string()),
+        ];
+        assert_eq!(
+            outputs(
+                "f()((address,bool,int256[]),uint8[2],bytes,string)",
+                &tokens
+            ),
+            json!([
+                [
+                    "0x0000000000000000000000000000000000000abc",
+                    true,
+                    ["-1", "2"]
+                ],
+                ["1", "2"],
+                "0xdead",
+                "USDC"
+            ])
+        );
+    }
+
+    #[test]
+    fn measurement_reads_decimal_strings() {
+        let key = |value_type, decimals| CallKeyConfig {
+            key: "supply".to_string(),
+            path: ".[0]".to_string(),
+            value_type,
+            decimals,
+        };
+        let huge = token_json(Token::Uint(U256::MAX));
+        assert!(matches!(
+            measurement(&key(None, Some(18)), &huge),
+            Ok(Measurement::Amount(amount)) if amount == Amount::new(U256::MAX, 18)
+        ));
+        assert!(matches!(
+            measurement(&key(Some(ValueType::Int), None), &token_json(int(-5))),
+            Ok(Measurement::Int(-5))
+        ));
+        assert_eq!(
+            measurement(&key(Some(ValueType::Int), None), &huge).unwrap_err(),
+            format!("supply: cannot convert {}", huge)
+        );
+    }
+}
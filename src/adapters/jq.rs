@@ -0,0 +1,35 @@
+use serde_json::Value;
+
+/// Compiles a jq filter, or `None` when it is not valid jq.
+pub fn compile_path(path: &str) -> Option<jaq_core::Filter<jaq_core::Native<jaq_json::Val>>> {
+    use jaq_core::load::{Arena, File, Loader};
+
+    let arena = Arena::default();
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let modules = loader
+        .load(
+            &arena,
+            File {
+                path: (),
+                code: path,
+            },
+        )
+        .ok()?;
+    jaq_core::Compiler::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+        .compile(modules)
+        .ok()
+}
+
+/// The first output of the jq filter `path` run over `json`, or the error jq
+/// stopped with.
+pub fn get_json_value_by_path(json: jaq_json::Val, path: &str) -> Result<Option<Value>, String> {
+    let filter = compile_path(path).ok_or_else(|| format!("invalid jq path {}", path))?;
+    let inputs = jaq_core::RcIter::new(core::iter::empty());
+    let mut out = filter.run((jaq_core::Ctx::new([], &inputs), json));
+    match out.next() {
+        Some(Ok(value)) => Ok(Some(value.into())),
+        Some(Err(e)) => Err(format!("{}: {}", path, e)),
+        None => Ok(None),
+    }
+}
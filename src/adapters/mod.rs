@@ -1,9 +1,15 @@
 pub mod base;
 pub mod btc;
+pub mod chainlink;
 pub mod compound;
 pub mod cosmos_bank;
 pub mod cosmwasm_smart_query;
 pub mod erc20;
 pub mod eth;
+pub mod evm_call;
+pub mod jq;
 pub mod morpho;
 pub mod morphov2;
+pub mod multicall;
+pub mod pyth;
+pub mod snapshot;
@@ -1,10 +1,16 @@
-use super::base::MetricsAdapter;
+use crate::chains::{Chains, EvmProvider};
+use crate::check::check_evm;
+
+use super::base::{
+    AdapterConfig, Amount, EndpointConfig, ExportUnit, Family, Measurement, MetricsAdapter,
+    Observation, check_evm_address, check_evm_addresses, strs,
+};
+use super::snapshot::Snapshot;
 use ethers::types::{Address, U128};
 use ethers::utils::hex::ToHexExt;
 use ethers::{abi::Abi, types::U256};
-use std::collections::HashMap;
+use serde::Deserialize;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 fn mul_div_floor(a: U256, b: U256, c: U256) -> U256 {
     if c.is_zero() {
@@ -13,19 +19,61 @@ fn mul_div_floor(a: U256, b: U256, c: U256) -> U256 {
     a.saturating_mul(b) / c
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct MorphoConfig {
+    pub addresses: Vec<String>,
+    pub contract: String,
+    pub decimals: u8,
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for MorphoConfig {
+    // Cents, from scaling by `decimals - 2`.
+    const LEGACY_UNIT: ExportUnit = ExportUnit::Fixed(2);
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_evm_addresses(&mut problems, "addresses", &self.addresses);
+        check_evm_address(&mut problems, "contract", &self.contract);
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        Ok(Box::new(
+            MorphoAdapter::new(
+                name,
+                strs(&self.addresses),
+                &self.contract,
+                chains.evm(self.endpoint.as_endpoint())?,
+                self.decimals,
+            )
+            .await?,
+        ))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let client = chains.evm(self.endpoint.as_endpoint())?;
+        check_evm(client, Some(&self.contract)).await
+    }
+}
+
 pub struct MorphoAdapter {
     name: String,
     addresses: Vec<String>,
     token: String,
     decimals: u8,
-    contract: ethers::contract::ContractInstance<
-        Arc<ethers::providers::Provider<ethers::providers::Http>>,
-        ethers::providers::Provider<ethers::providers::Http>,
-    >,
-    main_contract: ethers::contract::ContractInstance<
-        Arc<ethers::providers::Provider<ethers::providers::Http>>,
-        ethers::providers::Provider<ethers::providers::Http>,
-    >,
+    contract: ethers::contract::ContractInstance<Arc<EvmProvider>, EvmProvider>,
+    main_contract: ethers::contract::ContractInstance<Arc<EvmProvider>, EvmProvider>,
 }
 
 #[async_trait::async_trait]
@@ -34,24 +82,21 @@ impl MetricsAdapter for MorphoAdapter {
         &self.name
     }
 
-    async fn update_params(
-        &mut self,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut storage = metrics.lock().await;
-        self.update_balances(&mut storage).await?;
-        self.update_free_liquidity(&mut storage).await?;
-        Ok(())
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::evm(&self.contract.client()).await?;
+        let mut observations = self.fetch_balances(&snapshot).await?;
+        observations.extend(self.fetch_free_liquidity(&snapshot).await?);
+        observations.extend(snapshot.observations());
+        Ok(observations)
     }
 }
 
 impl MorphoAdapter {
     pub async fn new(
         name: &str,
-        metrics: Arc<Mutex<HashMap<String, super::base::Value>>>,
         addresses: Vec<&str>,
         contract: &str,
-        rpc: &str,
+        client: Arc<EvmProvider>,
         decimals: u8,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let abi: Abi = serde_json::from_str(
@@ -63,42 +108,13 @@ impl MorphoAdapter {
             [{"inputs": [{"internalType": "address","name": "newOwner","type": "address"}],"stateMutability": "nonpayable","type": "constructor"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": false,"internalType": "uint256","name": "prevBorrowRate","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "interest","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "feeShares","type": "uint256"}],"name": "AccrueInterest","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": false,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "onBehalf","type": "address"},{"indexed": true,"internalType": "address","name": "receiver","type": "address"},{"indexed": false,"internalType": "uint256","name": "assets","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "shares","type": "uint256"}],"name": "Borrow","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"indexed": false,"internalType": "struct MarketParams","name": "marketParams","type": "tuple"}],"name": "CreateMarket","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "address","name": "irm","type": "address"}],"name": "EnableIrm","type": "event"},{"anonymous": false,"inputs": [{"indexed": false,"internalType": "uint256","name": "lltv","type": "uint256"}],"name": "EnableLltv","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "token","type": "address"},{"indexed": false,"internalType": "uint256","name": "assets","type": "uint256"}],"name": "FlashLoan","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "authorizer","type": "address"},{"indexed": false,"internalType": "uint256","name": "usedNonce","type": "uint256"}],"name": "IncrementNonce","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": true,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "borrower","type": "address"},{"indexed": false,"internalType": "uint256","name": "repaidAssets","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "repaidShares","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "seizedAssets","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "badDebtAssets","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "badDebtShares","type": "uint256"}],"name": "Liquidate","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": true,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "onBehalf","type": "address"},{"indexed": false,"internalType": "uint256","name": "assets","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "shares","type": "uint256"}],"name": "Repay","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "authorizer","type": "address"},{"indexed": true,"internalType": "address","name": "authorized","type": "address"},{"indexed": false,"internalType": "bool","name": "newIsAuthorized","type": "bool"}],"name": "SetAuthorization","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": false,"internalType": "uint256","name": "newFee","type": "uint256"}],"name": "SetFee","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "address","name": "newFeeRecipient","type": "address"}],"name": "SetFeeRecipient","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "address","name": "newOwner","type": "address"}],"name": "SetOwner","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": true,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "onBehalf","type": "address"},{"indexed": false,"internalType": "uint256","name": "assets","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "shares","type": "uint256"}],"name": "Supply","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": true,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "onBehalf","type": "address"},{"indexed": false,"internalType": "uint256","name": "assets","type": "uint256"}],"name": "SupplyCollateral","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": false,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "onBehalf","type": "address"},{"indexed": true,"internalType": "address","name": "receiver","type": "address"},{"indexed": false,"internalType": "uint256","name": "assets","type": "uint256"},{"indexed": false,"internalType": "uint256","name": "shares","type": "uint256"}],"name": "Withdraw","type": "event"},{"anonymous": false,"inputs": [{"indexed": true,"internalType": "Id","name": "id","type": "bytes32"},{"indexed": false,"internalType": "address","name": "caller","type": "address"},{"indexed": true,"internalType": "address","name": "onBehalf","type": "address"},{"indexed": true,"internalType": "address","name": "receiver","type": "address"},{"indexed": false,"internalType": "uint256","name": "assets","type": "uint256"}],"name": "WithdrawCollateral","type": "event"},{"inputs": [],"name": "DOMAIN_SEPARATOR","outputs": [{"internalType": "bytes32","name": "","type": "bytes32"}],"stateMutability": "view","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"}],"name": "accrueInterest","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "uint256","name": "assets","type": "uint256"},{"internalType": "uint256","name": "shares","type": "uint256"},{"internalType": "address","name": "onBehalf","type": "address"},{"internalType": "address","name": "receiver","type": "address"}],"name": "borrow","outputs": [{"internalType": "uint256","name": "","type": "uint256"},{"internalType": "uint256","name": "","type": "uint256"}],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"}],"name": "createMarket","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "address","name": "irm","type": "address"}],"name": "enableIrm","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "uint256","name": "lltv","type": "uint256"}],"name": "enableLltv","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "bytes32[]","name": "slots","type": "bytes32[]"}],"name": "extSloads","outputs": [{"internalType": "bytes32[]","name": "res","type": "bytes32[]"}],"stateMutability": "view","type": "function"},{"inputs": [],"name": "feeRecipient","outputs": [{"internalType": "address","name": "","type": "address"}],"stateMutability": "view","type": "function"},{"inputs": [{"internalType": "address","name": "token","type": "address"},{"internalType": "uint256","name": "assets","type": "uint256"},{"internalType": "bytes","name": "data","type": "bytes"}],"name": "flashLoan","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "Id","name": "","type": "bytes32"}],"name": "idToMarketParams","outputs": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"stateMutability": "view","type": "function"},{"inputs": [{"internalType": "address","name": "","type": "address"},{"internalType": "address","name": "","type": "address"}],"name": "isAuthorized","outputs": [{"internalType": "bool","name": "","type": "bool"}],"stateMutability": "view","type": "function"},{"inputs": [{"internalType": "address","name": "","type": "address"}],"name": "isIrmEnabled","outputs": [{"internalType": "bool","name": "","type": "bool"}],"stateMutability": "view","type": "function"},{"inputs": [{"internalType": "uint256","name": "","type": "uint256"}],"name": "isLltvEnabled","outputs": [{"internalType": "bool","name": "","type": "bool"}],"stateMutability": "view","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "address","name": "borrower","type": "address"},{"internalType": "uint256","name": "seizedAssets","type": "uint256"},{"internalType": "uint256","name": "repaidShares","type": "uint256"},{"internalType": "bytes","name": "data","type": "bytes"}],"name": "liquidate","outputs": [{"internalType": "uint256","name": "","type": "uint256"},{"internalType": "uint256","name": "","type": "uint256"}],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "Id","name": "","type": "bytes32"}],"name": "market","outputs": [{"internalType": "uint128","name": "totalSupplyAssets","type": "uint128"},{"internalType": "uint128","name": "totalSupplyShares","type": "uint128"},{"internalType": "uint128","name": "totalBorrowAssets","type": "uint128"},{"internalType": "uint128","name": "totalBorrowShares","type": "uint128"},{"internalType": "uint128","name": "lastUpdate","type": "uint128"},{"internalType": "uint128","name": "fee","type": "uint128"}],"stateMutability": "view","type": "function"},{"inputs": [{"internalType": "address","name": "","type": "address"}],"name": "nonce","outputs": [{"internalType": "uint256","name": "","type": "uint256"}],"stateMutability": "view","type": "function"},{"inputs": [],"name": "owner","outputs": [{"internalType": "address","name": "","type": "address"}],"stateMutability": "view","type": "function"},{"inputs": [{"internalType": "Id","name": "","type": "bytes32"},{"internalType": "address","name": "","type": "address"}],"name": "position","outputs": [{"internalType": "uint256","name": "supplyShares","type": "uint256"},{"internalType": "uint128","name": "borrowShares","type": "uint128"},{"internalType": "uint128","name": "collateral","type": "uint128"}],"stateMutability": "view","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "uint256","name": "assets","type": "uint256"},{"internalType": "uint256","name": "shares","type": "uint256"},{"internalType": "address","name": "onBehalf","type": "address"},{"internalType": "bytes","name": "data","type": "bytes"}],"name": "repay","outputs": [{"internalType": "uint256","name": "","type": "uint256"},{"internalType": "uint256","name": "","type": "uint256"}],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "address","name": "authorized","type": "address"},{"internalType": "bool","name": "newIsAuthorized","type": "bool"}],"name": "setAuthorization","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "authorizer","type": "address"},{"internalType": "address","name": "authorized","type": "address"},{"internalType": "bool","name": "isAuthorized","type": "bool"},{"internalType": "uint256","name": "nonce","type": "uint256"},{"internalType": "uint256","name": "deadline","type": "uint256"}],"internalType": "struct Authorization","name": "authorization","type": "tuple"},{"components": [{"internalType": "uint8","name": "v","type": "uint8"},{"internalType": "bytes32","name": "r","type": "bytes32"},{"internalType": "bytes32","name": "s","type": "bytes32"}],"internalType": "struct Signature","name": "signature","type": "tuple"}],"name": "setAuthorizationWithSig","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "uint256","name": "newFee","type": "uint256"}],"name": "setFee","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "address","name": "newFeeRecipient","type": "address"}],"name": "setFeeRecipient","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"internalType": "address","name": "newOwner","type": "address"}],"name": "setOwner","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "uint256","name": "assets","type": "uint256"},{"internalType": "uint256","name": "shares","type": "uint256"},{"internalType": "address","name": "onBehalf","type": "address"},{"internalType": "bytes","name": "data","type": "bytes"}],"name": "supply","outputs": [{"internalType": "uint256","name": "","type": "uint256"},{"internalType": "uint256","name": "","type": "uint256"}],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "uint256","name": "assets","type": "uint256"},{"internalType": "address","name": "onBehalf","type": "address"},{"internalType": "bytes","name": "data","type": "bytes"}],"name": "supplyCollateral","outputs": [],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "uint256","name": "assets","type": "uint256"},{"internalType": "uint256","name": "shares","type": "uint256"},{"internalType": "address","name": "onBehalf","type": "address"},{"internalType": "address","name": "receiver","type": "address"}],"name": "withdraw","outputs": [{"internalType": "uint256","name": "","type": "uint256"},{"internalType": "uint256","name": "","type": "uint256"}],"stateMutability": "nonpayable","type": "function"},{"inputs": [{"components": [{"internalType": "address","name": "loanToken","type": "address"},{"internalType": "address","name": "collateralToken","type": "address"},{"internalType": "address","name": "oracle","type": "address"},{"internalType": "address","name": "irm","type": "address"},{"internalType": "uint256","name": "lltv","type": "uint256"}],"internalType": "struct MarketParams","name": "marketParams","type": "tuple"},{"internalType": "uint256","name": "assets","type": "uint256"},{"internalType": "address","name": "onBehalf","type": "address"},{"internalType": "address","name": "receiver","type": "address"}],"name": "withdrawCollateral","outputs": [],"stateMutability": "nonpayable","type": "function"}]
             "#,
         )?;
-        let provider = ethers::prelude::Provider::<ethers::providers::Http>::try_from(rpc)?;
-        let client = Arc::new(provider);
         let token_address: ethers::types::Address = contract.parse()?;
         let contract = ethers::contract::Contract::new(token_address, abi, client.clone());
         let main_token_address: ethers::types::Address =
             contract.method("MORPHO", ())?.call().await?;
         let main_contract =
             ethers::contract::Contract::new(main_token_address, main_abi, client.clone());
-        let mut storage = metrics.lock().await;
-        for addr in addresses.clone() {
-            let key = format!("{}_balance_{}", name, addr);
-            let value = super::base::Value::Int(prometheus::IntGauge::new(
-                &key,
-                format!("Value of {}", key),
-            )?);
-            storage.insert(key, value);
-        }
-        let key = format!(
-            "avaliable_liquidity_{}",
-            token_address.encode_hex_with_prefix()
-        );
-        storage.insert(
-            format!("{}_{}", name, &key),
-            super::base::Value::Int(prometheus::IntGauge::new(
-                &format!("{}_{}", name, &key),
-                format!("Free liquidity for {}", token_address),
-            )?),
-        );
-        let key = format!("idle_liquidity_{}", token_address.encode_hex_with_prefix());
-        storage.insert(
-            format!("{}_{}", name, &key),
-            super::base::Value::Int(prometheus::IntGauge::new(
-                &format!("{}_{}", name, &key),
-                format!("Idle liquidity for {}", token_address),
-            )?),
-        );
+        let vault = token_address.encode_hex_with_prefix();
 
         Ok(Self {
             addresses: addresses
@@ -107,59 +123,61 @@ impl MorphoAdapter {
                 .map(|addr| addr.parse().unwrap())
                 .collect(),
             contract,
-            token: token_address.encode_hex_with_prefix(),
+            token: vault,
             main_contract,
             name: name.to_string(),
             decimals,
         })
     }
 
-    async fn update_balances(
-        &mut self,
-        storage: &mut HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn fetch_balances(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let mut observations = Vec::new();
         for addr in self.addresses.clone() {
             let address: ethers::types::Address = addr.parse()?;
             let balance_shares: U256 = self
                 .contract
                 .method::<_, U256>("balanceOf", address)?
+                .block(snapshot.block())
                 .call()
                 .await?;
             let balance: U256 = self
                 .contract
                 .method::<_, U256>("convertToAssets", balance_shares)?
+                .block(snapshot.block())
                 .call()
-                .await?
-                .checked_div(U256::from(10).pow(self.decimals.into()))
-                .unwrap();
-            let value = storage.get(&self.get_key(&format!("balance_{}", addr)));
-            match value {
-                Some(super::base::Value::Int(v)) => {
-                    v.set(balance.as_u64() as i64);
-                }
-                _ => unreachable!(),
-            }
+                .await?;
+            observations.push(Observation::new(
+                Family::Balance,
+                &[&addr, &self.token, ""],
+                Measurement::Amount(Amount::new(balance, self.decimals)),
+                format!("balance_{}", addr),
+            ));
         }
-        Ok(())
+        Ok(observations)
     }
 
-    async fn update_free_liquidity(
-        &mut self,
-        storage: &mut std::collections::HashMap<String, super::base::Value>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn fetch_free_liquidity(
+        &self,
+        snapshot: &Snapshot,
+    ) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
         let withdraw_queue_length: U256 = self
             .contract
             .method::<_, U256>("withdrawQueueLength", ())?
+            .block(snapshot.block())
             .call()
             .await?;
 
         let mut free_liquidity = U256::zero();
-        let mut idle_liquidity = U128::zero();
+        let mut idle_liquidity = U256::zero();
 
-        for queue_index in 0..withdraw_queue_length.as_u64() {
+        for queue_index in 0..u64::try_from(withdraw_queue_length)? {
             let market_id: [u8; 32] = self
                 .contract
                 .method::<_, [u8; 32]>("withdrawQueue", U256::from(queue_index))?
+                .block(snapshot.block())
                 .call()
                 .await?;
 
@@ -173,6 +191,7 @@ impl MorphoAdapter {
             ): (U128, U128, U128, U128, U128, U128) = self
                 .main_contract
                 .method::<_, (U128, U128, U128, U128, U128, U128)>("market", market_id)?
+                .block(snapshot.block())
                 .call()
                 .await?;
 
@@ -188,23 +207,22 @@ impl MorphoAdapter {
                     "idToMarketParams",
                     market_id,
                 )?
+                .block(snapshot.block())
                 .call()
                 .await?;
 
             let (supply_shares, _borrow_shares, _collateral_assets): (U256, U128, U128) = self
                 .main_contract
                 .method::<_, (U256, U128, U128)>("position", (market_id, self.contract.address()))?
+                .block(snapshot.block())
                 .call()
                 .await?;
 
             if lltv.is_zero() && collateral_token.is_zero() && oracle.is_zero() && irm.is_zero() {
-                idle_liquidity = idle_liquidity.saturating_add(U128::from(
-                    mul_div_floor(
-                        supply_shares.into(),
-                        total_supply_assets.into(),
-                        total_supply_shares.into(),
-                    )
-                    .as_u128(),
+                idle_liquidity = idle_liquidity.saturating_add(mul_div_floor(
+                    supply_shares,
+                    total_supply_assets.into(),
+                    total_supply_shares.into(),
                 ));
                 continue;
             }
@@ -217,29 +235,26 @@ impl MorphoAdapter {
 
             let market_available = total_supply_assets
                 .checked_sub(total_borrow_assets)
-                .unwrap();
+                .ok_or("a market borrows more than is supplied")?;
 
             let vault_available = vault_supply_assets.min(market_available.into());
 
             free_liquidity = free_liquidity.saturating_add(vault_available);
         }
 
-        let key = format!("idle_liquidity_{}", self.token);
-        match storage.get(&self.get_key(&key)) {
-            Some(super::base::Value::Int(v)) => {
-                v.set(idle_liquidity.as_u64() as i64);
-            }
-            _ => unreachable!(),
-        }
-
-        let key = format!("avaliable_liquidity_{}", self.token);
-        match storage.get(&self.get_key(&key)) {
-            Some(super::base::Value::Int(v)) => {
-                v.set(free_liquidity.as_u64() as i64);
-            }
-            _ => unreachable!(),
-        }
-
-        Ok(())
+        Ok(vec![
+            Observation::new(
+                Family::IdleLiquidity,
+                &[&self.token],
+                Measurement::Amount(Amount::new(idle_liquidity, self.decimals)),
+                format!("idle_liquidity_{}", self.token),
+            ),
+            Observation::new(
+                Family::AvailableLiquidity,
+                &[&self.token],
+                Measurement::Amount(Amount::new(free_liquidity, self.decimals)),
+                format!("avaliable_liquidity_{}", self.token),
+            ),
+        ])
     }
 }
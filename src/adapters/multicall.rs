@@ -0,0 +1,316 @@
+use crate::chains::EvmProvider;
+use ethers::contract::{ContractCall, MULTICALL_ADDRESS, Multicall};
+use ethers::providers::{Middleware, ProviderError};
+use ethers::types::{Address, U64, U256};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+/// Calls per Multicall3 request unless the chain sets `multicall_batch_size`.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// A read returning a single `uint256`.
+pub enum Read {
+    /// Native balance of an address, `getEthBalance` in a batch.
+    Balance(Address),
+    Call(Box<ContractCall<EvmProvider, U256>>),
+}
+
+impl Read {
+    /// Names the read in errors.
+    fn describe(&self) -> String {
+        match self {
+            Read::Balance(address) => format!("balance of {:?}", address),
+            Read::Call(call) => match call.tx.to_addr() {
+                Some(contract) => format!("{} on {:?}", call.function.name, contract),
+                None => call.function.name.clone(),
+            },
+        }
+    }
+}
+
+/// Runs reads at a single block, batched through Multicall3 where it is
+/// deployed and one by one where it is not or batching is turned off.
+pub struct BatchReader {
+    client: Arc<EvmProvider>,
+    batch_size: usize,
+    deployed: OnceCell<bool>,
+}
+
+impl BatchReader {
+    /// A `batch_size` of 0 turns batching off.
+    pub fn new(client: Arc<EvmProvider>, batch_size: usize) -> Self {
+        Self {
+            client,
+            batch_size,
+            deployed: OnceCell::new(),
+        }
+    }
+
+    pub fn client(&self) -> &EvmProvider {
+        &self.client
+    }
+
+    /// Runs every read at `block` and returns the results in order, or an
+    /// error naming the first read that failed.
+    pub async fn read(
+        &self,
+        reads: Vec<Read>,
+        block: U64,
+    ) -> Result<Vec<U256>, Box<dyn std::error::Error>> {
+        if self.batch_size == 0 || !self.multicall_deployed().await? {
+            return self.read_each(reads, block).await;
+        }
+
+        let mut multicall = Multicall::new_with_chain_id(
+            Arc::clone(&self.client),
+            Some(MULTICALL_ADDRESS),
+            None::<u64>,
+        )?
+        .block(block);
+        let mut values = Vec::with_capacity(reads.len());
+        let mut reads = reads.into_iter().peekable();
+        while reads.peek().is_some() {
+            multicall.clear_calls();
+            let mut batch = Vec::with_capacity(self.batch_size);
+            // Failures are allowed so that one failed read can be named
+            // instead of the whole batch reverting.
+            for read in reads.by_ref().take(self.batch_size) {
+                batch.push(read.describe());
+                match read {
+                    Read::Balance(address) => multicall.add_get_eth_balance(address, true),
+                    Read::Call(call) => multicall.add_call(*call, true),
+                };
+            }
+            for (result, read) in multicall.call_raw().await?.into_iter().zip(batch) {
+                let value = result
+                    .ok()
+                    .and_then(|token| token.into_uint())
+                    .ok_or_else(|| format!("{} failed in multicall", read))?;
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+
+    async fn read_each(
+        &self,
+        reads: Vec<Read>,
+        block: U64,
+    ) -> Result<Vec<U256>, Box<dyn std::error::Error>> {
+        let mut values = Vec::with_capacity(reads.len());
+        for read in reads {
+            let described = read.describe();
+            let value = match read {
+                Read::Balance(address) => self
+                    .client
+                    .get_balance(address, Some(block.into()))
+                    .await
+                    .map_err(|e| e.to_string()),
+                Read::Call(call) => call.block(block).call().await.map_err(|e| e.to_string()),
+            };
+            values.push(value.map_err(|e| format!("{}: {}", described, e))?);
+        }
+        Ok(values)
+    }
+
+    /// Whether Multicall3 is deployed at its usual address, checked once.
+    async fn multicall_deployed(&self) -> Result<bool, ProviderError> {
+        let deployed = self
+            .deployed
+            .get_or_try_init(|| async {
+                let code = self.client.get_code(MULTICALL_ADDRESS, None).await?;
+                if code.is_empty() {
+                    eprintln!(
+                        "Multicall3 is not deployed on this chain, reading one call at a time"
+                    );
+                }
+                Ok::<_, ProviderError>(!code.is_empty())
+            })
+            .await?;
+        Ok(*deployed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chains::{Chains, Endpoint};
+    use ethers::abi::{self, ParamType, Token};
+    use ethers::utils::hex;
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use warp::Filter;
+
+    fn address(n: u8) -> Address {
+        Address::from_low_u64_be(n.into())
+    }
+
+    /// The balance the mock node holds for `address`, none for addresses
+    /// ending in `ff`, whose reads fail.
+    fn balance(address: Address) -> Option<U256> {
+        let last = address.as_bytes()[19];
+        (last != 0xff).then(|| U256::from(last) * 1000)
+    }
+
+    /// Serves balances over JSON-RPC, with or without Multicall3, and records
+    /// the number of calls in every multicall request.
+    async fn node(multicall: bool) -> (Arc<EvmProvider>, Arc<Mutex<Vec<usize>>>) {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&batches);
+        let route = warp::post()
+            .and(warp::body::json())
+            .map(move |request: Value| {
+                let params = &request["params"];
+                let result = match request["method"].as_str().unwrap() {
+                    "eth_getCode" => Ok(json!(if multicall { "0x6080" } else { "0x" })),
+                    "eth_getBalance" => {
+                        let address = params[0].as_str().unwrap().parse().unwrap();
+                        balance(address)
+                            .map(|value| json!(value))
+                            .ok_or("no balance")
+                    }
+                    "eth_call" => aggregate(&params[0], &recorded).map(|result| json!(result)),
+                    method => panic!("unexpected {}", method),
+                };
+                warp::reply::json(&match result {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                    Err(message) => json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "error": {"code": -32000, "message": message},
+                    }),
+                })
+            });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let rpc = format!("http://{}", address);
+        let endpoint = Endpoint {
+            chain: None,
+            rpc: Some(&rpc),
+            chain_id: None,
+        };
+        let client = Chains::new(HashMap::new()).evm(endpoint).unwrap();
+        (client, batches)
+    }
+
+    /// Answers an `aggregate3` call made of `getEthBalance` calls, reverting
+    /// like Multicall3 when a call that is not allowed to fail does.
+    fn aggregate(transaction: &Value, batches: &Mutex<Vec<usize>>) -> Result<String, &'static str> {
+        let data = transaction
+            .get("input")
+            .or(transaction.get("data"))
+            .and_then(Value::as_str)
+            .unwrap();
+        let data = hex::decode(data).unwrap();
+        let call = ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes]);
+        let calls = abi::decode(&[ParamType::Array(Box::new(call))], &data[4..]).unwrap();
+        let calls = calls[0].clone().into_array().unwrap();
+        batches.lock().unwrap().push(calls.len());
+        let mut results = Vec::new();
+        for call in calls {
+            let call = call.into_tuple().unwrap();
+            let allow_failure = call[1].clone().into_bool().unwrap();
+            let data = call[2].clone().into_bytes().unwrap();
+            // The selector, then the address padded to 32 bytes.
+            let value = balance(Address::from_slice(&data[16..36]));
+            if value.is_none() && !allow_failure {
+                return Err("execution reverted: Multicall3: call failed");
+            }
+            results.push(Token::Tuple(vec![
+                Token::Bool(value.is_some()),
+                Token::Bytes(value.map_or(Vec::new(), |value| abi::encode(&[Token::Uint(value)]))),
+            ]));
+        }
+        Ok(format!(
+            "0x{}",
+            hex::encode(abi::encode(&[Token::Array(results)]))
+        ))
+    }
+
+    fn balances(addresses: &[u8]) -> Vec<Read> {
+        addresses
+            .iter()
+            .map(|n| Read::Balance(address(*n)))
+            .collect()
+    }
+
+    fn thousands(values: &[u64]) -> Vec<U256> {
+        values
+            .iter()
+            .map(|value| U256::from(value * 1000))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn read_batches_by_batch_size() {
+        let (client, batches) = node(true).await;
+        let reader = BatchReader::new(client, 2);
+        let values = reader
+            .read(balances(&[1, 2, 3, 4, 5]), U64::from(7))
+            .await
+            .unwrap();
+        assert_eq!(values, thousands(&[1, 2, 3, 4, 5]));
+        assert_eq!(*batches.lock().unwrap(), [2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn read_fits_a_whole_batch_in_one_request() {
+        let (client, batches) = node(true).await;
+        let reader = BatchReader::new(client, 3);
+        let values = reader
+            .read(balances(&[1, 2, 3]), U64::from(7))
+            .await
+            .unwrap();
+        assert_eq!(values, thousands(&[1, 2, 3]));
+        assert_eq!(*batches.lock().unwrap(), [3]);
+    }
+
+    #[tokio::test]
+    async fn read_fails_when_a_batched_call_fails() {
+        let (client, _) = node(true).await;
+        let reader = BatchReader::new(client, 100);
+        let error = reader
+            .read(balances(&[1, 0xff, 3]), U64::from(7))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("balance of {:?} failed in multicall", address(0xff))
+        );
+    }
+
+    #[tokio::test]
+    async fn read_calls_one_at_a_time_without_multicall() {
+        let (client, batches) = node(false).await;
+        let reader = BatchReader::new(client, 2);
+        let values = reader
+            .read(balances(&[1, 2, 3]), U64::from(7))
+            .await
+            .unwrap();
+        assert_eq!(values, thousands(&[1, 2, 3]));
+        assert!(batches.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_calls_one_at_a_time_with_batching_off() {
+        let (client, batches) = node(true).await;
+        let reader = BatchReader::new(client, 0);
+        let values = reader.read(balances(&[1, 2]), U64::from(7)).await.unwrap();
+        assert_eq!(values, thousands(&[1, 2]));
+        assert!(batches.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_fails_when_a_single_call_fails() {
+        let (client, _) = node(false).await;
+        let reader = BatchReader::new(client, 100);
+        let error = reader
+            .read(balances(&[1, 0xff]), U64::from(7))
+            .await
+            .unwrap_err();
+        let prefix = format!("balance of {:?}: ", address(0xff));
+        assert!(error.to_string().starts_with(&prefix), "{}", error);
+        assert!(error.to_string().contains("no balance"), "{}", error);
+    }
+}
@@ -0,0 +1,188 @@
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use tendermint::block::Height;
+
+use crate::chains::{Chains, CosmosClient, Network};
+use crate::check::check_cosmos;
+
+use super::base::{
+    AdapterConfig, EndpointConfig, Family, Measurement, MetricsAdapter, Observation,
+    check_cosmos_address,
+};
+use super::cosmwasm_smart_query::query_contract;
+use super::snapshot::Snapshot;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PythConfig {
+    pub contract: String,
+    pub assets: Vec<PythAsset>,
+    #[serde(flatten)]
+    pub endpoint: EndpointConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PythAsset {
+    pub ticker: String,
+    pub id: String, // price feed id, hex with or without 0x
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdapterConfig for PythConfig {
+    const DEFAULT_CHAIN: &'static str = "cosmos";
+    const NETWORK: Network = Network::Cosmos;
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        check_cosmos_address(&mut problems, "contract", &self.contract);
+        if self.assets.is_empty() {
+            problems.push("assets: no assets".to_string());
+        }
+        let mut tickers = HashSet::new();
+        for (i, asset) in self.assets.iter().enumerate() {
+            if !tickers.insert(&asset.ticker) {
+                problems.push(format!(
+                    "assets[{}].ticker: {} is listed twice",
+                    i, asset.ticker
+                ));
+            }
+            check_feed_id(&mut problems, &format!("assets[{}].id", i), &asset.id);
+        }
+        problems
+    }
+
+    fn endpoint_config(&self) -> Option<&EndpointConfig> {
+        Some(&self.endpoint)
+    }
+
+    async fn build(
+        &self,
+        name: &str,
+        chains: &Chains,
+    ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+        let client = chains.cosmos(self.endpoint.as_endpoint())?;
+        Ok(Box::new(PythAdapter::new(
+            name,
+            &self.contract,
+            self.assets.clone(),
+            client,
+        )))
+    }
+
+    async fn check(&self, chains: &Chains) -> Result<String, Box<dyn std::error::Error>> {
+        let client = chains.cosmos(self.endpoint.as_endpoint())?;
+        check_cosmos(&client, Some(&self.contract)).await
+    }
+}
+
+pub fn check_feed_id(problems: &mut Vec<String>, field: &str, id: &str) {
+    let id = id.trim_start_matches("0x");
+    if id.len() != 64 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        problems.push(format!("{}: not a 32-byte hex feed id", field));
+    }
+}
+
+/// The `price_feed` answer of the Pyth contract. Prices are integers scaled
+/// by 10^`expo`.
+#[derive(Debug, Deserialize)]
+struct PriceFeedResponse {
+    price_feed: PriceFeed,
+}
+
+#[derive(Debug, Deserialize)]
+struct PriceFeed {
+    price: RawPrice,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawPrice {
+    price: String,
+    conf: String,
+    expo: i32,
+    publish_time: i64,
+}
+
+/// A price feed's latest price, scaled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub price: f64,
+    pub conf: f64,
+    pub publish_time: i64, // in seconds since the epoch
+}
+
+/// Reads the price of feed `id` from the Pyth contract.
+pub async fn query_price(
+    client: &CosmosClient,
+    contract: &str,
+    id: &str,
+    height: Option<Height>,
+) -> Result<Price, Box<dyn std::error::Error>> {
+    let query = json!({ "price_feed": { "id": id.trim_start_matches("0x") } });
+    let answer = query_contract(client, contract, &query.to_string(), height).await?;
+    let price = serde_json::from_value::<PriceFeedResponse>(answer)
+        .map_err(|e| format!("unexpected price feed: {}", e))?
+        .price_feed
+        .price;
+    let scale = 10f64.powi(price.expo);
+    Ok(Price {
+        price: price.price.parse::<f64>()? * scale,
+        conf: price.conf.parse::<f64>()? * scale,
+        publish_time: price.publish_time,
+    })
+}
+
+pub struct PythAdapter {
+    name: String,
+    contract: String,
+    assets: Vec<PythAsset>,
+    client: CosmosClient,
+}
+
+#[async_trait::async_trait]
+impl MetricsAdapter for PythAdapter {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+        let snapshot = Snapshot::cosmos(&self.client).await?;
+        let mut observations = snapshot.observations();
+        let height = Some(snapshot.height()?);
+        for asset in &self.assets {
+            let price = query_price(&self.client, &self.contract, &asset.id, height)
+                .await
+                .map_err(|e| format!("{}: {}", asset.ticker, e))?;
+
+            observations.push(Observation::new(
+                Family::Price,
+                &[&asset.ticker],
+                Measurement::Float(price.price),
+                format!("price_{}", asset.ticker),
+            ));
+            observations.push(Observation::new(
+                Family::PriceConfidence,
+                &[&asset.ticker],
+                Measurement::Float(price.conf),
+                format!("price_confidence_{}", asset.ticker),
+            ));
+            observations.push(Observation::new(
+                Family::PricePublishTime,
+                &[&asset.ticker],
+                Measurement::Int(price.publish_time),
+                format!("price_publish_time_{}", asset.ticker),
+            ));
+        }
+        Ok(observations)
+    }
+}
+
+impl PythAdapter {
+    pub fn new(name: &str, contract: &str, assets: Vec<PythAsset>, client: CosmosClient) -> Self {
+        Self {
+            name: name.to_string(),
+            contract: contract.to_string(),
+            assets,
+            client,
+        }
+    }
+}
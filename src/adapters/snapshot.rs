@@ -0,0 +1,64 @@
+use super::base::{Family, Measurement, Observation};
+use crate::chains::{CosmosClient, EvmProvider};
+use ethers::providers::Middleware;
+use ethers::types::{BlockId, U64};
+use tendermint::block::Height;
+
+/// The block all reads of one adapter run are pinned to, so its values are
+/// consistent with each other and can be told apart from a lagging RPC's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    pub height: u64,
+    pub timestamp: u64, // of the block, in seconds since the epoch
+}
+
+impl Snapshot {
+    /// The latest block of an EVM chain.
+    pub async fn evm(client: &EvmProvider) -> Result<Self, Box<dyn std::error::Error>> {
+        let height = client.get_block_number().await?;
+        let block = client
+            .get_block(height)
+            .await?
+            .ok_or_else(|| format!("block {} not found", height))?;
+        Ok(Self {
+            height: height.as_u64(),
+            timestamp: block.timestamp.as_u64(),
+        })
+    }
+
+    /// The latest height of a Cosmos chain.
+    pub async fn cosmos(client: &CosmosClient) -> Result<Self, Box<dyn std::error::Error>> {
+        let (height, time) = client.latest_block().await?;
+        Ok(Self {
+            height: height.value(),
+            timestamp: u64::try_from(time.unix_timestamp())?,
+        })
+    }
+
+    /// For EVM calls, as in `call.block(snapshot.block())`.
+    pub fn block(&self) -> BlockId {
+        BlockId::Number(U64::from(self.height).into())
+    }
+
+    /// For `abci_query`.
+    pub fn height(&self) -> Result<Height, Box<dyn std::error::Error>> {
+        Ok(Height::try_from(self.height)?)
+    }
+
+    pub fn observations(&self) -> Vec<Observation> {
+        vec![
+            Observation::new(
+                Family::SnapshotBlockHeight,
+                &[],
+                Measurement::Int(self.height as i64),
+                "snapshot_block_height".to_string(),
+            ),
+            Observation::new(
+                Family::SnapshotBlockTimestamp,
+                &[],
+                Measurement::Int(self.timestamp as i64),
+                "snapshot_block_timestamp".to_string(),
+            ),
+        ]
+    }
+}
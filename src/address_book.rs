@@ -0,0 +1,164 @@
+use crate::adapters::base::{Family, Measurement, Observation};
+use crate::config::{AddressInfo, Config};
+use crate::staleness::Share;
+use prometheus::{GaugeVec, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Checks every `[addresses.*]` entry, reporting problems as `field: message`.
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut addresses = config.addresses.keys().collect::<Vec<_>>();
+    addresses.sort();
+
+    let mut seen = HashMap::new();
+    for address in addresses {
+        let info = &config.addresses[address];
+        if let Some(other) = seen.insert(address.to_lowercase(), address) {
+            problems.push(format!(
+                "addresses.{}: same address as addresses.{}",
+                address, other
+            ));
+        }
+        if info.entity.is_empty() {
+            problems.push(format!("addresses.{}.entity: must not be empty", address));
+        }
+        for (i, tag) in info.tags.iter().enumerate() {
+            if tag.contains(',') {
+                problems.push(format!(
+                    "addresses.{}.tags[{}]: must not contain a comma",
+                    address, i
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// The entity, purpose and tags of every known address, and the balances of
+/// each entity summed per asset over all adapters into `pex_entity_balance`.
+/// Like the portfolio totals, the sums keep an adapter's last balances until
+/// it goes stale or is removed.
+#[derive(Clone)]
+pub struct AddressBook {
+    balances: GaugeVec,
+    state: Arc<Mutex<BookState>>,
+}
+
+#[derive(Default)]
+struct BookState {
+    entries: HashMap<String, AddressInfo>, // by lowercase address
+    holdings: HashMap<String, HashMap<[String; 3], f64>>, // by adapter, then entity, asset and symbol
+}
+
+impl AddressBook {
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let balances = GaugeVec::new(
+            Opts::new(
+                "pex_entity_balance",
+                "Balance of an entity summed over all of its addresses, in tokens",
+            ),
+            &["entity", "asset", "symbol"],
+        )?;
+        registry.register(Box::new(balances.clone()))?;
+        Ok(Self {
+            balances,
+            state: Arc::new(Mutex::new(BookState::default())),
+        })
+    }
+
+    /// Replaces the address book. Series labelled from the old one keep their
+    /// labels, so the runtime restarts the adapters when it changes.
+    pub fn set_entries(&self, entries: &HashMap<String, AddressInfo>) {
+        let mut state = self.state.lock().unwrap();
+        state.entries = entries
+            .iter()
+            .map(|(address, info)| (address.to_lowercase(), info.clone()))
+            .collect();
+    }
+
+    /// The holdings of the adapter `name`.
+    pub fn holdings(&self, name: &str) -> Holdings {
+        Holdings {
+            book: self.clone(),
+            adapter: name.to_string(),
+        }
+    }
+
+    fn export(&self, state: &BookState) {
+        let mut sums = HashMap::new();
+        for holdings in state.holdings.values() {
+            for (labels, amount) in holdings {
+                *sums.entry(labels).or_insert(0.0) += amount;
+            }
+        }
+        self.balances.reset();
+        for ([entity, asset, symbol], amount) in sums {
+            self.balances
+                .with_label_values(&[entity, asset, symbol])
+                .set(amount);
+        }
+    }
+}
+
+/// The balances of one adapter as seen by the address book.
+#[derive(Clone)]
+pub struct Holdings {
+    book: AddressBook,
+    adapter: String,
+}
+
+impl Holdings {
+    /// Appends the entity, purpose and tags of its address to the labels of
+    /// every balance, left empty for addresses not in the book.
+    pub fn label(&self, observations: &mut [Observation]) {
+        let state = self.book.state.lock().unwrap();
+        for observation in observations.iter_mut() {
+            if observation.family != Family::Balance {
+                continue;
+            }
+            let info = state.entries.get(&observation.labels[0].to_lowercase());
+            let (entity, purpose, tags) = match info {
+                Some(info) => (
+                    info.entity.clone(),
+                    info.purpose.clone(),
+                    info.tags.join(","),
+                ),
+                None => Default::default(),
+            };
+            observation.labels.extend([entity, purpose, tags]);
+        }
+    }
+}
+
+impl Share for Holdings {
+    /// Makes the labelled balances of known addresses the adapter's holdings.
+    fn settle(&self, observations: &[Observation]) {
+        let mut holdings = HashMap::new();
+        for observation in observations {
+            if observation.family != Family::Balance || observation.labels[3].is_empty() {
+                continue;
+            }
+            let amount = match observation.value {
+                Measurement::Int(value) => value as f64,
+                Measurement::Float(value) => value,
+                Measurement::Amount(amount) => amount.to_tokens(),
+            };
+            let [_, asset, symbol, entity, ..] = &observation.labels[..] else {
+                continue;
+            };
+            let labels = [entity.clone(), asset.clone(), symbol.clone()];
+            *holdings.entry(labels).or_insert(0.0) += amount;
+        }
+
+        let mut state = self.book.state.lock().unwrap();
+        state.holdings.insert(self.adapter.clone(), holdings);
+        self.book.export(&state);
+    }
+
+    fn withdraw(&self) {
+        let mut state = self.book.state.lock().unwrap();
+        state.holdings.remove(&self.adapter);
+        self.book.export(&state);
+    }
+}
@@ -0,0 +1,639 @@
+use crate::adapters::multicall::DEFAULT_BATCH_SIZE;
+use crate::config::{ChainConfig, ChainId, RpcMode};
+use ethers::providers::Middleware;
+use ethers::providers::{Http, HttpClientError, JsonRpcClient, JsonRpcError, Provider};
+use ethers::types::U64;
+use futures::future::join_all;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tendermint::Time;
+use tendermint::block::Height;
+use tendermint_rpc::endpoint::abci_query::{self, AbciQuery};
+use tendermint_rpc::{Client, HttpClient};
+use tokio::time::{Duration, Instant};
+
+pub type EvmProvider = Provider<EvmTransport>;
+
+/// The URLs a client connects to, how it uses them, and the rate limit that
+/// applies to each.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Connection {
+    pub urls: Vec<String>,
+    pub mode: RpcMode,
+    pub quorum: usize,
+    pub rate_limit: Option<u32>,
+}
+
+/// Where an adapter connects: a chain defined under `[chains]`, its own
+/// `rpc`, or both, in which case the adapter's `rpc` wins. Its `chain_id`
+/// likewise wins over the chain's.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Endpoint<'a> {
+    pub chain: Option<&'a str>,
+    pub rpc: Option<&'a str>,
+    pub chain_id: Option<&'a ChainId>,
+}
+
+/// The kind of chain an endpoint is on, which decides the kind of its chain id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Evm,
+    Cosmos,
+}
+
+/// Fails with a `field: message` problem when the chain id an endpoint is
+/// checked against is of the wrong kind for `network`: EVM chain ids are
+/// numbers and Cosmos ones are strings.
+pub fn check_chain_id(
+    definitions: &HashMap<String, ChainConfig>,
+    endpoint: Endpoint,
+    network: Network,
+) -> Result<(), String> {
+    let wrong = |chain_id: &ChainId| {
+        !matches!(
+            (chain_id, network),
+            (ChainId::Evm(_), Network::Evm) | (ChainId::Cosmos(_), Network::Cosmos)
+        )
+    };
+    let kind = match network {
+        Network::Evm => "a number on an EVM chain",
+        Network::Cosmos => "a string on a Cosmos chain",
+    };
+    let definition = endpoint.chain.and_then(|chain| definitions.get(chain));
+    match (endpoint.chain_id, endpoint.chain, definition) {
+        (Some(chain_id), _, _) if wrong(chain_id) => Err(format!("chain_id: must be {}", kind)),
+        (None, Some(chain), Some(definition))
+            if definition.chain_id.as_ref().is_some_and(wrong) =>
+        {
+            Err(format!(
+                "chain: the chain_id of chains.{} must be {}",
+                chain, kind
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Where an endpoint connects. Fails with a `field: message` problem when
+/// neither its chain nor its rpc says.
+pub fn resolve(
+    definitions: &HashMap<String, ChainConfig>,
+    endpoint: Endpoint,
+) -> Result<Connection, String> {
+    let definition = endpoint.chain.and_then(|chain| definitions.get(chain));
+    match (endpoint.rpc, definition, endpoint.chain) {
+        (Some(rpc), _, _) => Ok(Connection {
+            urls: vec![rpc.to_string()],
+            mode: RpcMode::Failover,
+            quorum: 1,
+            rate_limit: None,
+        }),
+        (None, Some(definition), _) => {
+            let urls = definition.rpc.urls().to_vec();
+            Ok(Connection {
+                quorum: definition.quorum.unwrap_or(urls.len() / 2 + 1),
+                urls,
+                mode: definition.rpc_mode,
+                rate_limit: definition.rate_limit,
+            })
+        }
+        (None, None, Some(chain)) => Err(format!(
+            "chain: no [chains.{}] to take the rpc from, and no rpc set",
+            chain
+        )),
+        (None, None, None) => {
+            Err("rpc: missing, set it or name a chain defined under [chains]".to_string())
+        }
+    }
+}
+
+/// Hands out one client per endpoint, so every adapter on a chain shares its
+/// connection pool and rate limit.
+pub struct Chains {
+    definitions: HashMap<String, ChainConfig>,
+    evm: Mutex<HashMap<Connection, Arc<EvmProvider>>>,
+    cosmos: Mutex<HashMap<Connection, CosmosClient>>,
+    verified: Mutex<HashSet<(Connection, ChainId)>>,
+}
+
+impl Chains {
+    pub fn new(definitions: HashMap<String, ChainConfig>) -> Self {
+        Self {
+            definitions,
+            evm: Mutex::new(HashMap::new()),
+            cosmos: Mutex::new(HashMap::new()),
+            verified: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Switches to new definitions. Clients are keyed by URL and rate limit,
+    /// so chains whose definition did not change keep their clients until
+    /// `retain` drops the unused ones.
+    pub fn reconfigure(&mut self, definitions: HashMap<String, ChainConfig>) {
+        self.definitions = definitions;
+    }
+
+    /// Forgets the clients and verified chain ids of connections that none of
+    /// `endpoints` resolves to. Adapters holding such a client keep using it
+    /// until they stop.
+    pub fn retain<'a>(&self, endpoints: impl IntoIterator<Item = Endpoint<'a>>) {
+        let used = endpoints
+            .into_iter()
+            .filter_map(|endpoint| resolve(&self.definitions, endpoint).ok())
+            .collect::<HashSet<_>>();
+        self.evm
+            .lock()
+            .unwrap()
+            .retain(|connection, _| used.contains(connection));
+        self.cosmos
+            .lock()
+            .unwrap()
+            .retain(|connection, _| used.contains(connection));
+        self.verified
+            .lock()
+            .unwrap()
+            .retain(|(connection, _)| used.contains(connection));
+    }
+
+    pub fn get(&self, chain: &str) -> Option<&ChainConfig> {
+        self.definitions.get(chain)
+    }
+
+    /// Calls per Multicall3 request on `chain`; 0 turns batching off.
+    pub fn batch_size(&self, chain: Option<&str>) -> usize {
+        chain
+            .and_then(|chain| self.get(chain))
+            .and_then(|chain| chain.multicall_batch_size)
+            .unwrap_or(DEFAULT_BATCH_SIZE)
+    }
+
+    /// The chain id an endpoint must report, if the adapter or its chain sets one.
+    pub fn expected_chain_id<'a>(&'a self, endpoint: Endpoint<'a>) -> Option<&'a ChainId> {
+        let definition = endpoint.chain.and_then(|chain| self.get(chain));
+        endpoint.chain_id.or_else(|| definition?.chain_id.as_ref())
+    }
+
+    /// Fails unless the endpoint reports its expected chain id, asking over
+    /// EVM JSON-RPC or Tendermint `status` as the id's kind says. Connections
+    /// are checked once.
+    pub async fn verify_chain_id(
+        &self,
+        endpoint: Endpoint<'_>,
+    ) -> Result<Option<ChainId>, Box<dyn std::error::Error>> {
+        let Some(expected) = self.expected_chain_id(endpoint) else {
+            return Ok(None);
+        };
+        let key = (resolve(&self.definitions, endpoint)?, expected.clone());
+        if self.verified.lock().unwrap().contains(&key) {
+            return Ok(Some(expected.clone()));
+        }
+        let reported = match expected {
+            ChainId::Evm(_) => ChainId::Evm(self.evm(endpoint)?.get_chainid().await?.as_u64()),
+            ChainId::Cosmos(_) => ChainId::Cosmos(
+                self.cosmos(endpoint)?
+                    .status()
+                    .await?
+                    .node_info
+                    .network
+                    .to_string(),
+            ),
+        };
+        if reported != *expected {
+            return Err(format!(
+                "wrong network: the rpc reports chain id {}, expected {}",
+                reported, expected
+            )
+            .into());
+        }
+        self.verified.lock().unwrap().insert(key);
+        Ok(Some(reported))
+    }
+
+    pub fn evm(&self, endpoint: Endpoint) -> Result<Arc<EvmProvider>, Box<dyn std::error::Error>> {
+        let key = resolve(&self.definitions, endpoint)?;
+        let mut clients = self.evm.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return Ok(Arc::clone(client));
+        }
+        let transport = EvmTransport {
+            upstreams: Upstreams::new(&key, |url| url.parse::<Http>())?,
+        };
+        let client = Arc::new(Provider::new(transport));
+        clients.insert(key, Arc::clone(&client));
+        Ok(client)
+    }
+
+    pub fn cosmos(&self, endpoint: Endpoint) -> Result<CosmosClient, Box<dyn std::error::Error>> {
+        let key = resolve(&self.definitions, endpoint)?;
+        let mut clients = self.cosmos.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = CosmosClient {
+            upstreams: Arc::new(Upstreams::new(&key, |url| {
+                HttpClient::builder(url.parse()?).build()
+            })?),
+        };
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+/// Spaces requests evenly so at most `per_second` start in any second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / per_second.max(1),
+            next: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        let at = (*next).max(Instant::now());
+        *next = at + self.interval;
+        drop(next);
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+/// One URL of a connection with its own rate limit.
+#[derive(Debug)]
+struct Upstream<C> {
+    client: C,
+    limiter: Option<RateLimiter>,
+}
+
+/// The URLs of a connection, used as its `rpc_mode` says.
+#[derive(Debug)]
+struct Upstreams<C> {
+    upstreams: Vec<Upstream<C>>,
+    mode: RpcMode,
+    quorum: usize,
+    next: AtomicUsize,
+}
+
+impl<C> Upstreams<C> {
+    fn new<E>(connection: &Connection, client: impl Fn(&str) -> Result<C, E>) -> Result<Self, E> {
+        let upstreams = connection
+            .urls
+            .iter()
+            .map(|url| {
+                Ok(Upstream {
+                    client: client(url)?,
+                    limiter: connection.rate_limit.map(RateLimiter::new),
+                })
+            })
+            .collect::<Result<Vec<_>, E>>()?;
+        Ok(Self {
+            upstreams,
+            mode: connection.mode,
+            quorum: connection.quorum,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// The first answer, trying the upstreams in failover or round-robin
+    /// order, or the last error when all of them fail.
+    async fn first<'a, T, E, F, Fut>(&'a self, request: F) -> Result<T, E>
+    where
+        F: Fn(&'a C) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let start = match self.mode {
+            RpcMode::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            RpcMode::Failover | RpcMode::Quorum => 0,
+        };
+        let mut last = None;
+        for i in 0..self.upstreams.len() {
+            let upstream = &self.upstreams[(start + i) % self.upstreams.len()];
+            if let Some(limiter) = &upstream.limiter {
+                limiter.wait().await;
+            }
+            match request(&upstream.client).await {
+                Ok(answer) => return Ok(answer),
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.expect("a connection has at least one URL"))
+    }
+
+    /// The answers of every upstream, asked at once.
+    async fn all<'a, T, E, F, Fut>(&'a self, request: F) -> Vec<Result<T, E>>
+    where
+        F: Fn(&'a C) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        join_all(self.upstreams.iter().map(|upstream| async {
+            if let Some(limiter) = &upstream.limiter {
+                limiter.wait().await;
+            }
+            request(&upstream.client).await
+        }))
+        .await
+    }
+
+    /// The highest block at least `quorum` upstreams have reached.
+    fn quorum_height<H: Ord + Copy>(&self, mut heights: Vec<H>) -> Option<H> {
+        heights.sort_unstable_by(|a, b| b.cmp(a));
+        heights.get(self.quorum - 1).copied()
+    }
+
+    /// The answer at least `quorum` upstreams agree on.
+    fn agreed<T: PartialEq>(&self, answers: Vec<T>) -> Option<T> {
+        let count = |answer: &T| answers.iter().filter(|other| *other == answer).count();
+        let at = answers
+            .iter()
+            .position(|answer| count(answer) >= self.quorum)?;
+        answers.into_iter().nth(at)
+    }
+
+    fn no_quorum(&self, what: &str) -> String {
+        format!(
+            "no {} of the {} rpc URLs agree on {}",
+            self.quorum,
+            self.upstreams.len(),
+            what
+        )
+    }
+}
+
+/// An HTTP JSON-RPC transport over one or more URLs that honours its chain's
+/// rate limit.
+#[derive(Debug)]
+pub struct EvmTransport {
+    upstreams: Upstreams<Http>,
+}
+
+#[async_trait::async_trait]
+impl JsonRpcClient for EvmTransport {
+    type Error = HttpClientError;
+
+    /// In quorum mode `eth_blockNumber` answers the block a quorum has
+    /// reached, so reads pinned to it can agree.
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = &params;
+        if self.upstreams.mode != RpcMode::Quorum {
+            return self
+                .upstreams
+                .first(|http| http.request(method, params))
+                .await;
+        }
+
+        let mut answers = Vec::new();
+        let mut error = None;
+        let results = self
+            .upstreams
+            .all(|http| http.request::<_, serde_json::Value>(method, params))
+            .await;
+        for result in results {
+            match result {
+                Ok(answer) => answers.push(answer),
+                Err(e) => error = Some(e),
+            }
+        }
+        if let (true, Some(error)) = (answers.is_empty(), error) {
+            return Err(error);
+        }
+        let agreed = match method {
+            "eth_blockNumber" => {
+                let heights = answers
+                    .into_iter()
+                    .filter_map(|answer| serde_json::from_value::<U64>(answer).ok())
+                    .collect();
+                self.upstreams
+                    .quorum_height(heights)
+                    .map(|height| serde_json::json!(height))
+            }
+            _ => self.upstreams.agreed(answers),
+        };
+        let answer = agreed.ok_or_else(|| JsonRpcError {
+            code: -32000,
+            message: self.upstreams.no_quorum(method),
+            data: None,
+        })?;
+        serde_json::from_value(answer.clone()).map_err(|err| HttpClientError::SerdeJson {
+            err,
+            text: answer.to_string(),
+        })
+    }
+}
+
+/// A Tendermint RPC client over one or more URLs that honours its chain's
+/// rate limit. Clones share the connections and the limit.
+#[derive(Clone)]
+pub struct CosmosClient {
+    upstreams: Arc<Upstreams<HttpClient>>,
+}
+
+impl CosmosClient {
+    /// The latest height and the time of its block. In quorum mode this is the
+    /// highest height `quorum` URLs have reached, so queries pinned to it can
+    /// agree.
+    pub async fn latest_block(&self) -> Result<(Height, Time), tendermint_rpc::Error> {
+        if self.upstreams.mode != RpcMode::Quorum {
+            let status = self.status().await?;
+            let info = status.sync_info;
+            return Ok((info.latest_block_height, info.latest_block_time));
+        }
+        let height = self.quorum_height().await?;
+        Ok((height, self.block(height).await?.block.header.time))
+    }
+
+    async fn quorum_height(&self) -> Result<Height, tendermint_rpc::Error> {
+        let heights = self
+            .upstreams
+            .all(|http| http.status())
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|status| status.sync_info.latest_block_height)
+            .collect();
+        self.upstreams.quorum_height(heights).ok_or_else(|| {
+            tendermint_rpc::Error::client_internal(self.upstreams.no_quorum("the latest height"))
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl tendermint_rpc::Client for CosmosClient {
+    /// Quorum mode compares `abci_query` answers only; other requests fail
+    /// over like in the default mode.
+    async fn perform<R>(&self, request: R) -> Result<R::Output, tendermint_rpc::Error>
+    where
+        R: tendermint_rpc::SimpleRequest,
+    {
+        // Requests cannot be cloned, so each attempt parses its own copy.
+        let json = request.into_json();
+        self.upstreams
+            .first(|http| async { http.perform(R::from_string(&json)?).await })
+            .await
+    }
+
+    async fn abci_query<V>(
+        &self,
+        path: Option<String>,
+        data: V,
+        height: Option<Height>,
+        prove: bool,
+    ) -> Result<AbciQuery, tendermint_rpc::Error>
+    where
+        V: Into<Vec<u8>> + Send,
+    {
+        let request = abci_query::Request::new(path, data, height, prove);
+        if self.upstreams.mode != RpcMode::Quorum {
+            return Ok(self.perform(request).await?.response);
+        }
+
+        let no_quorum =
+            |what| tendermint_rpc::Error::client_internal(self.upstreams.no_quorum(what));
+        let height = match height {
+            Some(height) => height,
+            None => self.quorum_height().await?,
+        };
+        let answers = self
+            .upstreams
+            .all(|http| {
+                let request = abci_query::Request {
+                    height: Some(height),
+                    ..request.clone()
+                };
+                async move { Ok::<_, tendermint_rpc::Error>(http.perform(request).await?.response) }
+            })
+            .await;
+        let answers = answers
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|answer| ((answer.code, answer.value.clone()), answer))
+            .collect::<Vec<_>>();
+        let keys = answers
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        let agreed = self
+            .upstreams
+            .agreed(keys)
+            .ok_or_else(|| no_quorum("abci_query"))?;
+        Ok(answers
+            .into_iter()
+            .find(|(key, _)| *key == agreed)
+            .map(|(_, answer)| answer)
+            .expect("the agreed key comes from an answer"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Rpc;
+
+    fn upstreams(urls: usize, quorum: usize) -> Upstreams<()> {
+        let connection = Connection {
+            urls: (0..urls).map(|i| format!("http://rpc{}", i)).collect(),
+            mode: RpcMode::Quorum,
+            quorum,
+            rate_limit: None,
+        };
+        Upstreams::new(&connection, |_| Ok::<_, ()>(())).unwrap()
+    }
+
+    #[test]
+    fn quorum_height_is_reached_by_a_quorum() {
+        let upstreams = upstreams(3, 2);
+        assert_eq!(upstreams.quorum_height(vec![100, 102, 101]), Some(101));
+        assert_eq!(upstreams.quorum_height(vec![100, 100, 90]), Some(100));
+    }
+
+    #[test]
+    fn quorum_height_counts_only_answers() {
+        let upstreams = upstreams(3, 2);
+        assert_eq!(upstreams.quorum_height(vec![105, 100]), Some(100));
+        assert_eq!(upstreams.quorum_height(vec![105]), None);
+        assert_eq!(upstreams.quorum_height(Vec::<u64>::new()), None);
+    }
+
+    #[test]
+    fn quorum_height_of_all_upstreams() {
+        let upstreams = upstreams(3, 3);
+        assert_eq!(upstreams.quorum_height(vec![7, 9, 8]), Some(7));
+    }
+
+    #[test]
+    fn agreed_by_a_quorum() {
+        let upstreams = upstreams(3, 2);
+        assert_eq!(upstreams.agreed(vec!["a", "b", "a"]), Some("a"));
+        assert_eq!(upstreams.agreed(vec!["b", "a", "a"]), Some("a"));
+        assert_eq!(upstreams.agreed(vec!["a", "a"]), Some("a"));
+    }
+
+    #[test]
+    fn agreed_without_a_quorum() {
+        let upstreams = upstreams(3, 2);
+        assert_eq!(upstreams.agreed(vec!["a", "b", "c"]), None);
+        assert_eq!(upstreams.agreed(vec!["a"]), None);
+        assert_eq!(upstreams.agreed(Vec::<&str>::new()), None);
+    }
+
+    #[test]
+    fn agreed_by_a_single_upstream() {
+        let upstreams = upstreams(1, 1);
+        assert_eq!(upstreams.agreed(vec![42]), Some(42));
+    }
+
+    fn chain(rpc: &str) -> ChainConfig {
+        ChainConfig {
+            rpc: Rpc::One(rpc.to_string()),
+            rpc_mode: RpcMode::Failover,
+            quorum: None,
+            chain_id: None,
+            native_symbol: None,
+            rate_limit: None,
+            multicall_batch_size: None,
+        }
+    }
+
+    fn endpoint(chain: &str) -> Endpoint<'_> {
+        Endpoint {
+            chain: Some(chain),
+            ..Endpoint::default()
+        }
+    }
+
+    #[test]
+    fn retain_drops_clients_of_changed_chains() {
+        let mut chains = Chains::new(HashMap::from([
+            ("a".to_string(), chain("http://a")),
+            ("b".to_string(), chain("http://b")),
+        ]));
+        let a = chains.evm(endpoint("a")).unwrap();
+        chains.evm(endpoint("b")).unwrap();
+        let own = Endpoint {
+            rpc: Some("http://own"),
+            ..Endpoint::default()
+        };
+        chains.evm(own).unwrap();
+
+        chains.reconfigure(HashMap::from([
+            ("a".to_string(), chain("http://a")),
+            ("b".to_string(), chain("http://b2")),
+        ]));
+        chains.retain([endpoint("a"), endpoint("b")]);
+        assert_eq!(chains.evm.lock().unwrap().len(), 1);
+        // The unchanged chain keeps its client.
+        assert!(Arc::ptr_eq(&a, &chains.evm(endpoint("a")).unwrap()));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::chains::{CosmosClient, EvmProvider};
+use cosmos_sdk_proto::traits::Message;
+use ethers::providers::Middleware;
+use std::error::Error;
+use std::sync::Arc;
+use tendermint_rpc::Client;
+
+/// Checks that an EVM endpoint answers and, if given, that a contract is
+/// deployed at `contract`. Returns the chain id for the report.
+pub async fn check_evm(
+    provider: Arc<EvmProvider>,
+    contract: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let chain_id = provider.get_chainid().await?;
+    if let Some(contract) = contract {
+        let code = provider
+            .get_code(contract.parse::<ethers::types::Address>()?, None)
+            .await?;
+        if code.is_empty() {
+            return Err(format!("no contract at {} on chain {}", contract, chain_id).into());
+        }
+    }
+    Ok(format!("chain id {}", chain_id))
+}
+
+/// Like `check_evm` for every contract in `contracts`, reporting each one
+/// that is missing rather than only the first.
+pub async fn check_evm_contracts<'a>(
+    provider: Arc<EvmProvider>,
+    contracts: impl Iterator<Item = &'a str>,
+) -> Result<String, Box<dyn Error>> {
+    let found = check_evm(Arc::clone(&provider), None).await?;
+    let mut count = 0;
+    let mut missing = Vec::new();
+    for contract in contracts {
+        count += 1;
+        if let Err(e) = check_evm(Arc::clone(&provider), Some(contract)).await {
+            missing.push(e.to_string());
+        }
+    }
+    if missing.is_empty() {
+        Ok(format!("{}, {} contracts", found, count))
+    } else {
+        Err(missing.join("; ").into())
+    }
+}
+
+/// Checks that a Cosmos endpoint answers and, if given, that a CosmWasm
+/// contract exists at `contract`. Returns the chain id for the report.
+pub async fn check_cosmos(
+    client: &CosmosClient,
+    contract: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let chain_id = client.status().await?.node_info.network;
+    if let Some(contract) = contract {
+        let path = Some("/cosmwasm.wasm.v1.Query/ContractInfo".to_string());
+        let req = cosmos_sdk_proto::cosmwasm::wasm::v1::QueryContractInfoRequest {
+            address: contract.to_string(),
+        };
+        let answer = client
+            .abci_query(path, req.encode_to_vec(), None, false)
+            .await?;
+        if answer.code.is_err() {
+            return Err(format!(
+                "no contract at {} on {}: {}",
+                contract, chain_id, answer.log
+            )
+            .into());
+        }
+    }
+    Ok(format!("chain id {}", chain_id))
+}
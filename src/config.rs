@@ -1,25 +1,479 @@
+use crate::adapters::base::ExportUnit;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
+use std::sync::RwLock;
+use std::time::Duration;
 
-#[derive(Debug, Deserialize)]
+/// Secrets of the configs in use, hidden from logs by `redact`.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+// Shorter substitutions are too likely to appear in unrelated text.
+const MIN_SECRET_LEN: usize = 4;
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub concurrency: u64,
-    pub interval: u64, // in seconds
+    pub interval: u64,        // in seconds
+    pub timeout: Option<u64>, // in seconds an update may take before it fails
+    #[serde(default)]
+    pub legacy_metric_names: bool, // also export the old flat per-address metric names
+    #[serde(default)]
+    pub chains: HashMap<String, ChainConfig>,
+    #[serde(default)]
+    pub prices: HashMap<String, PriceConfig>, // by ticker
+    #[serde(default)]
+    pub addresses: HashMap<String, AddressInfo>, // the address book
     pub metrics: HashMap<String, MetricConfig>,
+    #[serde(skip)]
+    pub secrets: Vec<String>, // values read from `env:` and `file:`
+}
+
+/// A network adapters connect to by naming it with `chain = "<name>"`. All
+/// adapters on a chain share one client, and with it the rate limit.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ChainConfig {
+    pub rpc: Rpc,
+    #[serde(default)]
+    pub rpc_mode: RpcMode, // how several `rpc` URLs are used
+    pub quorum: Option<usize>, // URLs that must agree in quorum mode, a majority by default
+    pub chain_id: Option<ChainId>,
+    pub native_symbol: Option<String>, // asset label of native balances
+    pub rate_limit: Option<u32>,       // max requests per second to each `rpc` URL
+    pub multicall_batch_size: Option<usize>, // calls per Multicall3 request, 0 turns batching off
+}
+
+/// One RPC URL or several.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Rpc {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Rpc {
+    pub fn urls(&self) -> &[String] {
+        match self {
+            Rpc::One(url) => std::slice::from_ref(url),
+            Rpc::Many(urls) => urls,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcMode {
+    /// Use the first URL, and the next ones only while it fails.
+    #[default]
+    Failover,
+    /// Send each request to the next URL in turn, moving on while one fails.
+    RoundRobin,
+    /// Ask every URL and accept an answer only when `quorum` of them agree
+    /// on it at the same block.
+    Quorum,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(untagged)]
+pub enum ChainId {
+    /// EIP-155 chain id, e.g. 1 for Ethereum mainnet.
+    Evm(u64),
+    /// Cosmos chain id, e.g. "celestia".
+    Cosmos(String),
+}
+
+impl std::fmt::Display for ChainId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainId::Evm(id) => write!(f, "{}", id),
+            ChainId::Cosmos(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Who an address belongs to and what for, added as labels to its balances.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AddressInfo {
+    pub entity: String, // e.g. "Treasury", balances are also summed per entity
+    #[serde(default)]
+    pub purpose: String,
+    #[serde(default)]
+    pub tags: Vec<String>, // exported comma-separated
+}
+
+/// Where the USD price of some assets comes from. Balances whose `asset`
+/// label is one of `assets` are valued with it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PriceConfig {
+    pub assets: Vec<String>, // contract addresses, denoms, or "BTC" and the native symbols
+    pub max_age: Option<u64>, // in seconds a price outlives its source, 3 intervals by default
+    #[serde(flatten)]
+    pub source: PriceSource,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum PriceSource {
+    /// A fixed price, e.g. 1 for a USD stablecoin.
+    Static { peg: f64 },
+    /// A Pyth price feed on a CosmWasm chain.
+    Pyth {
+        contract: String,
+        id: String,
+        chain: Option<String>,
+        rpc: Option<String>,
+    },
+    /// A Chainlink aggregator on an EVM chain.
+    Chainlink {
+        aggregator: String,
+        chain: Option<String>,
+        rpc: Option<String>,
+    },
+    /// A number picked by the jq `path` from a CosmWasm smart query.
+    Cosmwasm {
+        contract: String,
+        query: String,
+        path: String,
+        chain: Option<String>,
+        rpc: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct MetricConfig {
     pub enabled: bool,
     pub adapter: String,
+    pub interval: Option<u64>, // in seconds, overrides the global interval
+    pub timeout: Option<u64>,  // in seconds, overrides the global timeout
+    #[serde(default)]
+    pub jitter: u64, // max random delay before the first run, in seconds
+    #[serde(default)]
+    pub priority: i32, // higher runs first when concurrency slots are contended
+    #[serde(default)]
+    pub stale: StalenessConfig,
+    #[serde(default)]
+    pub unit: ExportUnit, // how token amounts are exported
+    pub group: Option<String>, // portfolio group the adapter's USD values add up to
     pub config: serde_json::Value,
 }
 
+/// What to do with an adapter's last exported values once its updates keep failing.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct StalenessConfig {
+    pub policy: StalePolicy,
+    pub after_failures: Option<u32>, // consecutive failed updates
+    pub max_age: Option<u64>,        // in seconds since the last success
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StalePolicy {
+    /// Keep exporting the last known value.
+    #[default]
+    Keep,
+    /// Export NaN. Integer gauges cannot hold NaN and are dropped instead.
+    Nan,
+    /// Stop exporting the series until the next successful update.
+    Drop,
+}
+
+impl StalenessConfig {
+    /// Without an explicit threshold a single failure makes the values stale.
+    pub fn is_stale(&self, failures: u32, since_success: Option<Duration>) -> bool {
+        let too_many_failures = failures >= self.after_failures.unwrap_or(1);
+        let too_old = match (self.max_age, since_success) {
+            (Some(max_age), Some(age)) => age >= Duration::from_secs(max_age),
+            _ => false,
+        };
+        match (self.after_failures, self.max_age) {
+            (None, Some(_)) => too_old,
+            _ => too_many_failures || too_old,
+        }
+    }
+}
+
+/// Every problem found in a config, reported together.
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl std::fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid config:")?;
+        for problem in &self.0 {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
 impl Config {
+    /// Reads the config and resolves references in every string value:
+    /// `env:NAME` and `file:PATH` replace the whole value with a secret from
+    /// an environment variable or a file, `${NAME}` expands a variable
+    /// anywhere, and `raw:` keeps the rest of the value as it is.
     pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let content = fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
+        let mut value: toml::Value = toml::from_str(&content)?;
+        let mut problems = Vec::new();
+        let mut secrets = Vec::new();
+        interpolate(&mut value, "", &mut problems, &mut secrets);
+        if !problems.is_empty() {
+            return Err(Box::new(ConfigErrors(problems)));
+        }
+        let mut config: Config = value.try_into()?;
+        config.secrets = secrets;
         Ok(config)
     }
 }
+
+/// Makes `redact` hide the secrets of `configs`, forgetting any others.
+pub fn mask_secrets(configs: &[&Config]) {
+    let mut secrets = configs
+        .iter()
+        .flat_map(|config| &config.secrets)
+        .filter(|secret| secret.len() >= MIN_SECRET_LEN)
+        .cloned()
+        .collect::<Vec<_>>();
+    // Longer secrets first, so one containing another is masked whole.
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    secrets.dedup();
+    *SECRETS.write().unwrap() = secrets;
+}
+
+/// Replaces every secret of the configs in use with `***`.
+pub fn redact(text: &str) -> String {
+    let secrets = SECRETS.read().unwrap();
+    secrets
+        .iter()
+        .fold(text.to_string(), |text, secret| text.replace(secret, "***"))
+}
+
+fn interpolate(
+    value: &mut toml::Value,
+    path: &str,
+    problems: &mut Vec<String>,
+    secrets: &mut Vec<String>,
+) {
+    match value {
+        toml::Value::String(text) => match resolve(text, secrets) {
+            Ok(Some(resolved)) => *text = resolved,
+            Ok(None) => {}
+            Err(e) => problems.push(format!("{}: {}", path, e)),
+        },
+        toml::Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                interpolate(item, &format!("{}[{}]", path, i), problems, secrets);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                interpolate(item, &path, problems, secrets);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the resolved value, or `None` when there is nothing to resolve.
+/// Values read from `env:` and `file:` are added to `secrets`.
+fn resolve(text: &str, secrets: &mut Vec<String>) -> Result<Option<String>, String> {
+    if let Some(literal) = text.strip_prefix("raw:") {
+        return Ok(Some(literal.to_string()));
+    }
+    if let Some(name) = text.strip_prefix("env:") {
+        let secret = env(name)?;
+        secrets.push(secret.clone());
+        return Ok(Some(secret));
+    }
+    if let Some(path) = text.strip_prefix("file:") {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read secret file {}: {}", path, e))?;
+        let secret = content.trim_end().to_string();
+        secrets.push(secret.clone());
+        return Ok(Some(secret));
+    }
+    if !text.contains("${") {
+        return Ok(None);
+    }
+
+    let mut resolved = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unterminated ${{ in {:?}", text))?;
+        resolved.push_str(&rest[..start]);
+        resolved.push_str(&env(&rest[start + 2..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(Some(resolved))
+}
+
+fn env(name: &str) -> Result<String, String> {
+    std::env::var(name).map_err(|_| format!("environment variable {} is not set", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn staleness(after_failures: Option<u32>, max_age: Option<u64>) -> StalenessConfig {
+        StalenessConfig {
+            policy: StalePolicy::Nan,
+            after_failures,
+            max_age,
+        }
+    }
+
+    #[test]
+    fn stale_after_one_failure_by_default() {
+        let config = staleness(None, None);
+        assert!(!config.is_stale(0, None));
+        assert!(config.is_stale(1, None));
+        assert!(config.is_stale(1, Some(Duration::from_secs(3600))));
+    }
+
+    #[test]
+    fn stale_after_failures() {
+        let config = staleness(Some(3), None);
+        assert!(!config.is_stale(2, Some(Duration::from_secs(3600))));
+        assert!(config.is_stale(3, Some(Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn stale_after_max_age_alone() {
+        let config = staleness(None, Some(60));
+        assert!(!config.is_stale(5, Some(Duration::from_secs(59))));
+        assert!(config.is_stale(1, Some(Duration::from_secs(60))));
+        // Without a success there is no age to compare.
+        assert!(!config.is_stale(5, None));
+    }
+
+    #[test]
+    fn stale_after_failures_or_max_age() {
+        let config = staleness(Some(3), Some(60));
+        assert!(!config.is_stale(2, Some(Duration::from_secs(59))));
+        assert!(config.is_stale(3, Some(Duration::from_secs(1))));
+        assert!(config.is_stale(1, Some(Duration::from_secs(60))));
+    }
+
+    // Every test sets its own variables, as tests run in parallel.
+    fn set_env(name: &str, value: &str) {
+        // SAFETY: no test reads or writes a variable another test sets.
+        unsafe { std::env::set_var(name, value) };
+    }
+
+    #[test]
+    fn resolve_leaves_plain_text() {
+        let mut secrets = Vec::new();
+        assert_eq!(
+            resolve("https://rpc.example.com", &mut secrets).unwrap(),
+            None
+        );
+        assert_eq!(resolve("", &mut secrets).unwrap(), None);
+        assert!(secrets.is_empty());
+    }
+
+    #[test]
+    fn resolve_env() {
+        set_env("PEX_TEST_RESOLVE_ENV", "secret-token");
+        let mut secrets = Vec::new();
+        assert_eq!(
+            resolve("env:PEX_TEST_RESOLVE_ENV", &mut secrets)
+                .unwrap()
+                .as_deref(),
+            Some("secret-token")
+        );
+        assert_eq!(secrets, ["secret-token"]);
+        assert!(
+            resolve("env:PEX_TEST_RESOLVE_UNSET", &mut secrets)
+                .unwrap_err()
+                .contains("PEX_TEST_RESOLVE_UNSET is not set")
+        );
+    }
+
+    #[test]
+    fn resolve_file() {
+        let path = std::env::temp_dir().join(format!("pex-test-resolve-{}", std::process::id()));
+        fs::write(&path, "file-secret\n").unwrap();
+        let mut secrets = Vec::new();
+        let resolved = resolve(&format!("file:{}", path.display()), &mut secrets);
+        fs::remove_file(&path).unwrap();
+        // The trailing newline is trimmed.
+        assert_eq!(resolved.unwrap().as_deref(), Some("file-secret"));
+        assert_eq!(secrets, ["file-secret"]);
+        assert!(
+            resolve("file:/nonexistent/pex-secret", &mut secrets)
+                .unwrap_err()
+                .starts_with("cannot read secret file /nonexistent/pex-secret")
+        );
+    }
+
+    #[test]
+    fn resolve_expands_variables() {
+        set_env("PEX_TEST_RESOLVE_HOST", "rpc.example.com");
+        set_env("PEX_TEST_RESOLVE_KEY", "abcd1234");
+        let mut secrets = Vec::new();
+        assert_eq!(
+            resolve(
+                "https://${PEX_TEST_RESOLVE_HOST}/v3/${PEX_TEST_RESOLVE_KEY}",
+                &mut secrets
+            )
+            .unwrap()
+            .as_deref(),
+            Some("https://rpc.example.com/v3/abcd1234")
+        );
+        // Expanded variables are not secrets.
+        assert!(secrets.is_empty());
+        assert!(resolve("${PEX_TEST_RESOLVE_UNSET_TOO}", &mut secrets).is_err());
+        assert!(
+            resolve("https://${PEX_TEST_RESOLVE_HOST", &mut secrets)
+                .unwrap_err()
+                .starts_with("unterminated ${")
+        );
+    }
+
+    #[test]
+    fn resolve_keeps_raw_values() {
+        let mut secrets = Vec::new();
+        assert_eq!(
+            resolve("raw:env:prod", &mut secrets).unwrap().as_deref(),
+            Some("env:prod")
+        );
+        assert_eq!(
+            resolve("raw:${NOT_EXPANDED}", &mut secrets)
+                .unwrap()
+                .as_deref(),
+            Some("${NOT_EXPANDED}")
+        );
+        assert!(secrets.is_empty());
+    }
+
+    #[test]
+    fn mask_secrets_replaces_the_masked_set() {
+        let config = |secrets: &[&str]| {
+            let mut config: Config =
+                toml::from_str("concurrency = 1\ninterval = 60\n[metrics]").unwrap();
+            config.secrets = secrets.iter().map(|secret| secret.to_string()).collect();
+            config
+        };
+        let old = config(&["old-secret", "abc"]);
+        let new = config(&["new-secret"]);
+
+        mask_secrets(&[&old, &new]);
+        assert_eq!(redact("old-secret new-secret"), "*** ***");
+        // Too short to mask without garbling other text.
+        assert_eq!(redact("abc"), "abc");
+
+        mask_secrets(&[&new]);
+        assert_eq!(redact("old-secret new-secret"), "old-secret ***");
+    }
+}
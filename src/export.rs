@@ -0,0 +1,337 @@
+use crate::adapters::base::{ExportUnit, Family, Measurement, Observation, Value};
+use prometheus::{Gauge, GaugeVec, IntGauge, Opts};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+/// The series of one adapter. Each family is a `GaugeVec` with the adapter
+/// name and chain as constant labels, so vectors registered by different
+/// adapters merge into one family on scrape. With legacy names enabled every
+/// series is also exported under its old flat metric name, with amounts in
+/// the legacy unit of the adapter.
+pub struct MetricSet {
+    adapter: String,
+    chain: String,
+    legacy: Option<ExportUnit>,
+    unit: ExportUnit,
+    vecs: HashMap<Family, GaugeVec>,
+    legacy_gauges: HashMap<String, Value>,
+    series: HashMap<String, (Family, Vec<String>)>, // (family, labels) by key, as last set
+}
+
+impl MetricSet {
+    pub fn new(adapter: &str, chain: &str, legacy: Option<ExportUnit>, unit: ExportUnit) -> Self {
+        Self {
+            adapter: adapter.to_string(),
+            chain: chain.to_string(),
+            legacy,
+            unit,
+            vecs: HashMap::new(),
+            legacy_gauges: HashMap::new(),
+            series: HashMap::new(),
+        }
+    }
+
+    /// Sets the series of every observation, creating the ones seen for the
+    /// first time and removing the ones no longer observed. Returns the
+    /// collectors created by this call, so they can be registered, and the
+    /// ones it retired, so they can be unregistered. Nothing is set unless
+    /// every value converts and every series can be created.
+    pub fn record(
+        &mut self,
+        observations: &[Observation],
+    ) -> Result<(Vec<Value>, Vec<Value>), Box<dyn std::error::Error>> {
+        let convert = |unit: ExportUnit| {
+            observations
+                .iter()
+                .map(|observation| match observation.value {
+                    Measurement::Int(value) => Ok(value as f64),
+                    Measurement::Float(value) => Ok(value),
+                    Measurement::Amount(amount) => amount.export(unit),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let values = convert(self.unit)?;
+        let legacy_values = match self.legacy {
+            Some(unit) => convert(unit)?,
+            None => Vec::new(),
+        };
+
+        // A series with the wrong labels would only fail once it is resolved.
+        if let Some(observation) = observations
+            .iter()
+            .find(|observation| observation.labels.len() != observation.family.labels().len())
+        {
+            return Err(format!(
+                "{}: {} labels, expected {}",
+                observation.key,
+                observation.labels.len(),
+                observation.family.labels().len()
+            )
+            .into());
+        }
+
+        // Every gauge is resolved, creating what is missing, before any is
+        // set, so an error leaves the series of the previous call untouched.
+        let mut vecs = HashMap::new();
+        let mut legacy_gauges = HashMap::<String, Value>::new();
+        let mut gauges = Vec::new();
+        for observation in observations {
+            let vec = match self.vecs.get(&observation.family) {
+                Some(vec) => vec,
+                None => match vecs.entry(observation.family) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let opts = Opts::new(observation.name(), observation.help())
+                            .const_label("adapter", &self.adapter)
+                            .const_label("chain", &self.chain);
+                        entry.insert(GaugeVec::new(opts, observation.family.labels())?)
+                    }
+                },
+            };
+            let labels = observation
+                .labels
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            let gauge = vec.get_metric_with_label_values(&labels)?;
+
+            let legacy = match self.legacy {
+                Some(_) => {
+                    let name = format!("{}_{}", self.adapter, observation.key);
+                    let legacy = match self.legacy_gauges.get(&name) {
+                        Some(gauge) => gauge.clone(),
+                        None => match legacy_gauges.entry(name) {
+                            Entry::Occupied(entry) => entry.get().clone(),
+                            Entry::Vacant(entry) => {
+                                let help = format!("Value of {}", entry.key());
+                                let gauge = match observation.value {
+                                    Measurement::Int(_) => {
+                                        Value::Int(IntGauge::new(entry.key(), help)?)
+                                    }
+                                    _ => Value::Float(Gauge::new(entry.key(), help)?),
+                                };
+                                entry.insert(gauge).clone()
+                            }
+                        },
+                    };
+                    Some(legacy)
+                }
+                None => None,
+            };
+            gauges.push((gauge, legacy));
+        }
+
+        for (i, ((gauge, legacy), value)) in gauges.into_iter().zip(values).enumerate() {
+            gauge.set(value);
+            match (legacy, legacy_values.get(i)) {
+                (Some(Value::Int(gauge)), Some(&value)) => gauge.set(value as i64),
+                (Some(Value::Float(gauge)), Some(&value)) => gauge.set(value),
+                _ => {}
+            }
+        }
+        let mut created = vecs.values().cloned().map(Value::Vec).collect::<Vec<_>>();
+        created.extend(legacy_gauges.values().cloned());
+        self.vecs.extend(vecs);
+        self.legacy_gauges.extend(legacy_gauges);
+
+        let series = observations
+            .iter()
+            .map(|observation| {
+                let series = (observation.family, observation.labels.clone());
+                (observation.key.clone(), series)
+            })
+            .collect::<HashMap<_, _>>();
+        let observed = series.values().collect::<HashSet<_>>();
+        let mut retired = Vec::new();
+        for (key, (family, labels)) in &self.series {
+            if !observed.contains(&(*family, labels.clone()))
+                && let Some(vec) = self.vecs.get(family)
+            {
+                let labels = labels.iter().map(String::as_str).collect::<Vec<_>>();
+                let _ = vec.remove_label_values(&labels);
+            }
+            if !series.contains_key(key)
+                && let Some(gauge) = self
+                    .legacy_gauges
+                    .remove(&format!("{}_{}", self.adapter, key))
+            {
+                retired.push(gauge);
+            }
+        }
+        self.series = series;
+        Ok((created, retired))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::base::Amount;
+    use ethers::types::U256;
+    use prometheus::Registry;
+    use prometheus::core::Collector;
+
+    fn balance(address: &str, raw: U256, decimals: u8) -> Observation {
+        Observation::new(
+            Family::Balance,
+            &[address, "ETH", "ETH", "", "", ""],
+            Measurement::Amount(Amount::new(raw, decimals)),
+            format!("balance_{}", address),
+        )
+    }
+
+    fn registry(created: &[Value]) -> Registry {
+        let registry = Registry::new();
+        for gauge in created {
+            match gauge {
+                Value::Int(g) => registry.register(Box::new(g.clone())),
+                Value::Float(g) => registry.register(Box::new(g.clone())),
+                Value::Vec(g) => registry.register(Box::new(g.clone())),
+            }
+            .unwrap();
+        }
+        registry
+    }
+
+    /// The values of the series named `name`, in the order they are gathered.
+    fn values(registry: &Registry, name: &str) -> Vec<f64> {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.get_name() == name)
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_gauge().get_value())
+            .collect()
+    }
+
+    #[test]
+    fn record_sets_every_family() {
+        let mut metrics = MetricSet::new("a", "ethereum", None, ExportUnit::Tokens);
+        let price = Observation::new(
+            Family::Price,
+            &["ETHUSD"],
+            Measurement::Float(2500.5),
+            "price_ETHUSD".to_string(),
+        );
+        let (created, retired) = metrics
+            .record(&[balance("0x1", U256::from(150), 2), price])
+            .unwrap();
+        assert_eq!(created.len(), 2);
+        assert!(retired.is_empty());
+
+        let registry = registry(&created);
+        assert_eq!(values(&registry, "pex_balance"), [1.5]);
+        assert_eq!(values(&registry, "pex_price"), [2500.5]);
+        let family = registry.gather();
+        let labels = family[0].get_metric()[0].get_label();
+        assert!(
+            labels
+                .iter()
+                .any(|pair| pair.get_name() == "adapter" && pair.get_value() == "a")
+        );
+    }
+
+    #[test]
+    fn record_exports_legacy_names_in_the_legacy_unit() {
+        let unit = ExportUnit::Fixed(2);
+        let mut metrics = MetricSet::new("a", "ethereum", Some(unit), ExportUnit::Tokens);
+        let height = Observation::new(
+            Family::SnapshotBlockHeight,
+            &[],
+            Measurement::Int(16),
+            "snapshot_block_height".to_string(),
+        );
+        let (created, _) = metrics
+            .record(&[balance("0x1", U256::from(1234), 3), height])
+            .unwrap();
+        assert_eq!(created.len(), 4);
+
+        let registry = registry(&created);
+        assert_eq!(values(&registry, "pex_balance"), [1.234]);
+        assert_eq!(values(&registry, "a_balance_0x1"), [123.0]);
+        assert!(
+            created
+                .iter()
+                .any(|gauge| matches!(gauge, Value::Int(g) if g.get() == 16))
+        );
+    }
+
+    #[test]
+    fn record_sets_nothing_when_a_value_does_not_convert() {
+        let mut metrics = MetricSet::new("a", "ethereum", None, ExportUnit::Fixed(2));
+        let (created, _) = metrics.record(&[balance("0x1", U256::from(1), 0)]).unwrap();
+        let registry = registry(&created);
+
+        let overflow = balance("0x2", U256::MAX, 0);
+        let result = metrics.record(&[balance("0x1", U256::from(2), 0), overflow]);
+        assert!(result.is_err());
+        assert_eq!(values(&registry, "pex_balance"), [100.0]);
+    }
+
+    #[test]
+    fn record_sets_nothing_when_a_series_has_the_wrong_labels() {
+        let mut metrics = MetricSet::new("a", "ethereum", None, ExportUnit::Tokens);
+        let (created, _) = metrics.record(&[balance("0x1", U256::from(1), 0)]).unwrap();
+        let registry = registry(&created);
+
+        let unlabelled = Observation::new(
+            Family::Price,
+            &[],
+            Measurement::Float(1.0),
+            "price".to_string(),
+        );
+        let result = metrics.record(&[balance("0x2", U256::from(2), 0), unlabelled]);
+        assert!(result.is_err());
+        assert_eq!(values(&registry, "pex_balance"), [1.0]);
+    }
+
+    #[test]
+    fn record_retires_series_no_longer_observed() {
+        let unit = ExportUnit::Raw;
+        let mut metrics = MetricSet::new("a", "ethereum", Some(unit), ExportUnit::Tokens);
+        let (created, _) = metrics
+            .record(&[
+                balance("0x1", U256::from(1), 0),
+                balance("0x2", U256::from(2), 0),
+            ])
+            .unwrap();
+        let registry = registry(&created);
+
+        let (created, retired) = metrics.record(&[balance("0x1", U256::from(3), 0)]).unwrap();
+        assert!(created.is_empty());
+        assert_eq!(retired.len(), 1);
+        assert!(matches!(&retired[0], Value::Float(g) if g.desc()[0].fq_name == "a_balance_0x2"));
+        assert_eq!(values(&registry, "pex_balance"), [3.0]);
+    }
+
+    #[test]
+    fn set_nan_spares_integer_gauges() {
+        let unit = ExportUnit::Raw;
+        let mut metrics = MetricSet::new("a", "ethereum", Some(unit), ExportUnit::Tokens);
+        let height = Observation::new(
+            Family::SnapshotBlockHeight,
+            &[],
+            Measurement::Int(16),
+            "snapshot_block_height".to_string(),
+        );
+        let (created, _) = metrics
+            .record(&[
+                balance("0x1", U256::from(1), 0),
+                balance("0x2", U256::from(2), 0),
+                height,
+            ])
+            .unwrap();
+        let registry = registry(&created);
+
+        // Only the legacy block height is an integer gauge.
+        let nan = created.iter().map(Value::set_nan).collect::<Vec<_>>();
+        assert_eq!(nan.iter().filter(|nan| !**nan).count(), 1);
+        assert!(
+            values(&registry, "pex_balance")
+                .iter()
+                .all(|value| value.is_nan())
+        );
+        assert!(values(&registry, "a_balance_0x1")[0].is_nan());
+        assert_eq!(values(&registry, "a_snapshot_block_height"), [16.0]);
+    }
+}
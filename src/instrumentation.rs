@@ -0,0 +1,93 @@
+use prometheus::{
+    GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+};
+use std::time::Duration;
+
+const LABELS: &[&str] = &["adapter", "type"];
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Metrics the exporter publishes about its own adapter runs, labelled by
+/// adapter name and adapter type.
+#[derive(Clone)]
+pub struct Instrumentation {
+    up: IntGaugeVec,
+    last_success: GaugeVec,
+    duration: HistogramVec,
+    errors: IntCounterVec,
+}
+
+impl Instrumentation {
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let up = IntGaugeVec::new(
+            Opts::new(
+                "pex_adapter_up",
+                "Whether the last update of the adapter succeeded",
+            ),
+            LABELS,
+        )?;
+        let last_success = GaugeVec::new(
+            Opts::new(
+                "pex_adapter_last_success_timestamp_seconds",
+                "Unix time of the last successful update of the adapter",
+            ),
+            LABELS,
+        )?;
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "pex_adapter_update_duration_seconds",
+                "Duration of adapter updates",
+            )
+            .buckets(DURATION_BUCKETS.to_vec()),
+            LABELS,
+        )?;
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "pex_adapter_errors_total",
+                "Number of failed adapter updates",
+            ),
+            LABELS,
+        )?;
+
+        registry.register(Box::new(up.clone()))?;
+        registry.register(Box::new(last_success.clone()))?;
+        registry.register(Box::new(duration.clone()))?;
+        registry.register(Box::new(errors.clone()))?;
+
+        Ok(Self {
+            up,
+            last_success,
+            duration,
+            errors,
+        })
+    }
+
+    /// Creates the error counter at zero so `increase()` sees the first failure.
+    pub fn init(&self, name: &str, adapter_type: &str) {
+        self.errors.with_label_values(&[name, adapter_type]);
+    }
+
+    pub fn observe(&self, name: &str, adapter_type: &str, elapsed: Duration, success: bool) {
+        let labels = [name, adapter_type];
+        self.duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+        if success {
+            self.up.with_label_values(&labels).set(1);
+            self.last_success
+                .with_label_values(&labels)
+                .set(chrono::Utc::now().timestamp() as f64);
+        } else {
+            self.up.with_label_values(&labels).set(0);
+            self.errors.with_label_values(&labels).inc();
+        }
+    }
+
+    /// Drops every series of an adapter that is no longer running.
+    pub fn remove(&self, name: &str, adapter_type: &str) {
+        let labels = [name, adapter_type];
+        let _ = self.up.remove_label_values(&labels);
+        let _ = self.last_success.remove_label_values(&labels);
+        let _ = self.duration.remove_label_values(&labels);
+        let _ = self.errors.remove_label_values(&labels);
+    }
+}
@@ -1,3 +1,13 @@
 pub mod adapter_factory;
+pub mod address_book;
 pub mod adapters;
+pub mod chains;
+pub mod check;
 pub mod config;
+pub mod export;
+pub mod instrumentation;
+pub mod pricing;
+pub mod runtime;
+pub mod scheduler;
+pub mod staleness;
+pub mod status;
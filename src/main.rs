@@ -1,83 +1,82 @@
-use portfolio_explorer::adapters::base::Value;
-use portfolio_explorer::config::Config;
-use portfolio_explorer::{adapter_factory::create_adapter, adapters::base::MetricsAdapter};
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::{Duration, sleep};
+use clap::{Parser, Subcommand};
+use portfolio_explorer::adapter_factory::{AdapterRegistry, log_warnings};
+use portfolio_explorer::chains::Chains;
+use portfolio_explorer::config::{Config, mask_secrets, redact};
+use portfolio_explorer::runtime::{Runtime, watch_config};
+use serde_json::json;
+use std::net::SocketAddr;
 use warp::Filter;
+use warp::http::StatusCode;
 
-use futures::stream::{FuturesUnordered, StreamExt};
-use tokio::sync::Semaphore;
+#[derive(Parser)]
+#[command(version, about = "Exports crypto portfolio metrics to Prometheus")]
+struct Cli {
+    /// Path of the config file
+    #[arg(long, short, global = true, default_value = "config.toml")]
+    config: String,
+
+    /// Address /metrics and the JSON API under /api/v1 listen on
+    #[arg(long, global = true, default_value = "0.0.0.0:9100")]
+    listen: SocketAddr,
+
+    /// Same as the `once` command, kept for existing scripts
+    #[arg(long, alias = "single-run", hide = true)]
+    once: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve /metrics and refresh adapters until stopped (the default)
+    Run,
+    /// Refresh every adapter once and print the metrics
+    Once,
+    /// Validate the config without exporting anything
+    CheckConfig {
+        /// Also connect to every enabled adapter's RPC and check that its contract exists
+        #[arg(long)]
+        connect: bool,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let single_run = std::env::args().any(|arg| arg == "--once" || arg == "--single-run");
-    let config = Config::from_file("config.toml")?;
-
-    let registry = Arc::new(prometheus::Registry::new());
-    let metrics = Arc::new(Mutex::new(HashMap::<String, Value>::new()));
-    let adapters = Arc::new(Mutex::new(HashMap::<
-        String,
-        Box<dyn MetricsAdapter + Send + Sync>,
-    >::new()));
-
-    {
-        let mut adapters_guard = adapters.lock().await;
-        for (name, config) in &config.metrics {
-            if config.enabled {
-                match create_adapter(name.to_string(), metrics.clone(), config).await {
-                    Ok(adapter) => {
-                        adapters_guard.insert(name.clone(), adapter);
-                    }
-                    Err(e) => eprintln!("Error initializing adapter {}: {}", name, e),
-                }
-            }
+    let cli = Cli::parse();
+    let command = match cli.command {
+        Some(command) => command,
+        None if cli.once => Command::Once,
+        None => Command::Run,
+    };
+    let config = match Config::from_file(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", cli.config, redact(&e.to_string()));
+            std::process::exit(1);
         }
-    }
+    };
+    mask_secrets(&[&config]);
 
-    {
-        let metrics_guard = metrics.lock().await;
-        for metric in metrics_guard.values() {
-            match metric {
-                Value::Int(gauge) => {
-                    registry.register(Box::new(gauge.clone()))?;
-                }
-                Value::Float(gauge) => {
-                    registry.register(Box::new(gauge.clone()))?;
-                }
-            }
+    if let Command::CheckConfig { connect } = command {
+        if !check_config(&config, connect).await {
+            std::process::exit(1);
         }
+        return Ok(());
     }
 
-    if single_run {
-        let adapters_clone = Arc::clone(&adapters);
-        let metrics_clone = Arc::clone(&metrics);
-        let semaphore = Arc::new(Semaphore::new(config.concurrency as usize));
-        let mut futures = FuturesUnordered::new();
-
-        {
-            let adapters_guard = adapters_clone.lock().await;
-            for (name, _) in adapters_guard.iter() {
-                let name = name.clone();
-                let adapters_clone = Arc::clone(&adapters_clone);
-                let metrics_clone = Arc::clone(&metrics_clone);
-                let semaphore_clone = Arc::clone(&semaphore);
-
-                futures.push(tokio::spawn(async move {
-                    let _permit = semaphore_clone.acquire().await.unwrap();
-                    let mut adapters_guard = adapters_clone.lock().await;
-                    if let Some(adapter) = adapters_guard.get_mut(&name) {
-                        match adapter.update_params(metrics_clone).await {
-                            Ok(_) => println!("Updated {}", name),
-                            Err(e) => eprintln!("Error updating {}: {}", name, e),
-                        }
-                    }
-                }));
-            }
+    let mut runtime = match Runtime::new(config) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("{}", redact(&e.to_string()));
+            std::process::exit(1);
         }
+    };
+    let registry = runtime.registry();
+    let status = runtime.status();
 
-        while futures.next().await.is_some() {}
+    if let Command::Once = command {
+        runtime.run_once().await;
 
         let mut buffer = String::new();
         let encoder = prometheus::TextEncoder::new();
@@ -87,51 +86,81 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    let registry_clone = registry.clone();
+    runtime.start().await;
+
+    let listen = cli.listen;
     tokio::spawn(async move {
         let metrics_route = warp::path!("metrics").map(move || {
             let mut buffer = String::new();
             let encoder = prometheus::TextEncoder::new();
-            let metric_families = registry_clone.gather();
+            let metric_families = registry.gather();
             encoder.encode_utf8(&metric_families, &mut buffer).unwrap();
             warp::reply::with_header(buffer, "Content-Type", "text/plain; version=0.0.4")
         });
 
-        warp::serve(metrics_route).run(([0, 0, 0, 0], 9100)).await;
-    });
-
-    let semaphore = Arc::new(Semaphore::new(config.concurrency as usize));
-
-    loop {
-        let mut futures = FuturesUnordered::new();
+        let portfolio = status.clone();
+        let portfolio_route = warp::path!("api" / "v1" / "portfolio")
+            .map(move || warp::reply::json(&portfolio.portfolio()));
+        let adapters = status.clone();
+        let adapters_route = warp::path!("api" / "v1" / "adapters")
+            .map(move || warp::reply::json(&adapters.adapters()));
+        let adapter_route =
+            warp::path!("api" / "v1" / "adapters" / String).map(move |name: String| {
+                match status.adapter(&name) {
+                    Some(adapter) => {
+                        warp::reply::with_status(warp::reply::json(&adapter), StatusCode::OK)
+                    }
+                    None => warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": format!("no adapter {}", name) })),
+                        StatusCode::NOT_FOUND,
+                    ),
+                }
+            });
 
-        let adapters_clone = Arc::clone(&adapters);
-        let metrics_clone = Arc::clone(&metrics);
-        let semaphore_clone = Arc::clone(&semaphore);
+        let routes = metrics_route
+            .or(portfolio_route)
+            .or(adapters_route)
+            .or(adapter_route);
+        warp::serve(routes).run(listen).await;
+    });
 
-        {
-            let adapters_guard = adapters_clone.lock().await; // Lock only during iteration
-            for (name, _) in adapters_guard.iter() {
-                let name = name.clone();
-                let adapters_clone = Arc::clone(&adapters);
-                let metrics_clone = Arc::clone(&metrics_clone);
-                let semaphore_clone = Arc::clone(&semaphore_clone);
+    runtime.run(watch_config(cli.config)).await;
+    Ok(())
+}
 
-                futures.push(tokio::spawn(async move {
-                    let _permit = semaphore_clone.acquire().await.unwrap(); // Acquire a permit
+/// Prints a report and returns whether the config is good to roll out.
+async fn check_config(config: &Config, connect: bool) -> bool {
+    let adapters = match AdapterRegistry::default().validate(config) {
+        Ok(adapters) => adapters,
+        Err(e) => {
+            eprintln!("{}", redact(&e.to_string()));
+            return false;
+        }
+    };
+    log_warnings(&adapters, |_| true);
+    println!("Config is valid: {} adapters", adapters.len());
+    if !connect {
+        return true;
+    }
 
-                    let mut adapters_guard = adapters_clone.lock().await;
-                    if let Some(adapter) = adapters_guard.get_mut(&name) {
-                        match adapter.update_params(metrics_clone).await {
-                            Ok(_) => println!("Updated {}", name),
-                            Err(e) => eprintln!("Error updating {}: {}", name, e),
-                        }
-                    }
-                }));
+    let mut names = config
+        .metrics
+        .iter()
+        .filter(|(_, metric)| metric.enabled)
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let chains = Chains::new(config.chains.clone());
+    let mut ok = true;
+    for name in names {
+        match adapters[name].check(&chains).await {
+            Ok(found) => println!("ok    metrics.{}: {}", name, found),
+            Err(e) => {
+                println!("FAIL  metrics.{}: {}", name, redact(&e.to_string()));
+                ok = false;
             }
         }
-
-        while let Some(_) = futures.next().await {} // Wait for all tasks to finish
-        sleep(Duration::from_secs(config.interval)).await;
     }
+    ok
 }
@@ -0,0 +1,454 @@
+use crate::adapters::base::{
+    Family, Measurement, Observation, check_cosmos_address, check_evm_address, check_url,
+};
+use crate::adapters::chainlink::latest_round;
+use crate::adapters::cosmwasm_smart_query::query_contract;
+use crate::adapters::jq::{compile_path, get_json_value_by_path};
+use crate::adapters::pyth::{check_feed_id, query_price};
+use crate::chains::{
+    Chains, CosmosClient, Endpoint, EvmProvider, Network, check_chain_id, resolve,
+};
+use crate::config::{Config, PriceConfig, PriceSource, redact};
+use crate::staleness::Share;
+use ethers::types::Address;
+use futures::future::join_all;
+use prometheus::{GaugeVec, Opts, Registry};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, MissedTickBehavior, timeout};
+
+/// Checks every `[prices.*]` entry, reporting problems as `field: message`.
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut tickers = config.prices.keys().collect::<Vec<_>>();
+    tickers.sort();
+
+    let mut priced = HashMap::new();
+    for ticker in tickers {
+        let price = &config.prices[ticker];
+        let mut found = Vec::new();
+        if price.assets.is_empty() {
+            found.push("assets: no assets".to_string());
+        }
+        if price
+            .max_age
+            .is_some_and(|max_age| max_age < config.interval)
+        {
+            found.push(format!(
+                "max_age: must be at least the interval of {}s",
+                config.interval
+            ));
+        }
+        for (i, asset) in price.assets.iter().enumerate() {
+            if let Some(other) = priced.insert(asset.to_lowercase(), ticker) {
+                found.push(format!(
+                    "assets[{}]: {} is already priced by prices.{}",
+                    i, asset, other
+                ));
+            }
+        }
+        match &price.source {
+            PriceSource::Static { peg } => {
+                if !peg.is_finite() || *peg < 0.0 {
+                    found.push("peg: must not be negative".to_string());
+                }
+            }
+            PriceSource::Pyth { contract, id, .. } => {
+                check_cosmos_address(&mut found, "contract", contract);
+                check_feed_id(&mut found, "id", id);
+            }
+            PriceSource::Chainlink { aggregator, .. } => {
+                check_evm_address(&mut found, "aggregator", aggregator)
+            }
+            PriceSource::Cosmwasm {
+                contract,
+                query,
+                path,
+                ..
+            } => {
+                check_cosmos_address(&mut found, "contract", contract);
+                if serde_json::from_str::<Value>(query).is_err() {
+                    found.push("query: invalid JSON".to_string());
+                }
+                if compile_path(path).is_none() {
+                    found.push("path: invalid jq path".to_string());
+                }
+            }
+        }
+        if let Some(endpoint) = endpoint(&price.source) {
+            if let Some(rpc) = endpoint.rpc {
+                check_url(&mut found, "rpc", rpc);
+            }
+            let network = match price.source {
+                PriceSource::Chainlink { .. } => Network::Evm,
+                _ => Network::Cosmos,
+            };
+            if let Err(problem) = resolve(&config.chains, endpoint)
+                .and_then(|_| check_chain_id(&config.chains, endpoint, network))
+            {
+                found.push(problem);
+            }
+        }
+        problems.extend(
+            found
+                .into_iter()
+                .map(|problem| format!("prices.{}.{}", ticker, problem)),
+        );
+    }
+    problems
+}
+
+/// Where a price source connects, or `None` for a static price.
+pub fn endpoint(source: &PriceSource) -> Option<Endpoint<'_>> {
+    match source {
+        PriceSource::Static { .. } => None,
+        PriceSource::Pyth { chain, rpc, .. }
+        | PriceSource::Chainlink { chain, rpc, .. }
+        | PriceSource::Cosmwasm { chain, rpc, .. } => Some(Endpoint {
+            chain: chain.as_deref(),
+            rpc: rpc.as_deref(),
+            chain_id: None,
+        }),
+    }
+}
+
+/// The latest USD price of every priced asset, until it expires, and the value the balances of
+/// each adapter added up to on its last update. Per-adapter values are summed
+/// into `pex_portfolio_value_usd` per group and into
+/// `pex_portfolio_total_value_usd`, which keep an adapter's last value until
+/// it goes stale or is removed.
+#[derive(Clone)]
+pub struct Portfolio {
+    prices: GaugeVec,
+    groups: GaugeVec,
+    total: GaugeVec,
+    state: Arc<Mutex<PortfolioState>>,
+}
+
+#[derive(Default)]
+struct PortfolioState {
+    prices: HashMap<String, (PriceConfig, f64, Instant)>, // (config, price, fetched at) by ticker
+    values: HashMap<String, (Option<String>, f64)>,       // (group, value) by adapter
+}
+
+impl Portfolio {
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let prices = GaugeVec::new(
+            Opts::new("pex_asset_price_usd", "USD price used to value an asset"),
+            &["ticker"],
+        )?;
+        let groups = GaugeVec::new(
+            Opts::new(
+                "pex_portfolio_value_usd",
+                "USD value of the priced balances of a group of adapters",
+            ),
+            &["group"],
+        )?;
+        // Without labels, so the series only appears once something is valued.
+        let total = GaugeVec::new(
+            Opts::new(
+                "pex_portfolio_total_value_usd",
+                "USD value of all priced balances",
+            ),
+            &[],
+        )?;
+
+        registry.register(Box::new(prices.clone()))?;
+        registry.register(Box::new(groups.clone()))?;
+        registry.register(Box::new(total.clone()))?;
+
+        Ok(Self {
+            prices,
+            groups,
+            total,
+            state: Arc::new(Mutex::new(PortfolioState::default())),
+        })
+    }
+
+    /// Values the balances of the adapter `name`, adding them to `group`.
+    pub fn valuation(&self, name: &str, group: Option<&str>) -> Valuation {
+        Valuation {
+            portfolio: self.clone(),
+            adapter: name.to_string(),
+            group: group.map(str::to_string),
+        }
+    }
+
+    /// Forgets the prices of tickers that are gone or configured differently.
+    pub fn keep_prices(&self, prices: &HashMap<String, PriceConfig>) {
+        let mut state = self.state.lock().unwrap();
+        state.prices.retain(|ticker, (config, ..)| {
+            let keep = prices.get(ticker) == Some(config);
+            if !keep {
+                let _ = self.prices.remove_label_values(&[ticker]);
+            }
+            keep
+        });
+    }
+
+    fn set_price(&self, ticker: &str, config: &PriceConfig, price: f64) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .prices
+            .insert(ticker.to_string(), (config.clone(), price, Instant::now()));
+        self.prices.with_label_values(&[ticker]).set(price);
+    }
+
+    /// Forgets the price of `ticker` once it was fetched `max_age` ago, so
+    /// its assets are no longer valued. Returns whether it did.
+    fn expire_price(&self, ticker: &str, max_age: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let expired = state
+            .prices
+            .get(ticker)
+            .is_some_and(|(.., fetched_at)| fetched_at.elapsed() >= max_age);
+        if expired {
+            state.prices.remove(ticker);
+            let _ = self.prices.remove_label_values(&[ticker]);
+        }
+        expired
+    }
+
+    /// The price of a balance's `asset` label. Assets match case-insensitively,
+    /// since EVM addresses are written both checksummed and in lowercase.
+    fn price(&self, asset: &str) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        state
+            .prices
+            .values()
+            .find(|(config, ..)| config.assets.iter().any(|a| a.eq_ignore_ascii_case(asset)))
+            .map(|(_, price, _)| *price)
+    }
+
+    fn export(&self, state: &PortfolioState) {
+        let mut groups = HashMap::new();
+        for (group, value) in state.values.values() {
+            if let Some(group) = group {
+                *groups.entry(group.as_str()).or_insert(0.0) += value;
+            }
+        }
+        self.groups.reset();
+        for (group, value) in groups {
+            self.groups.with_label_values(&[group]).set(value);
+        }
+        if state.values.is_empty() {
+            let _ = self.total.remove_label_values(&[]);
+        } else {
+            let total = state.values.values().map(|(_, value)| value).sum();
+            self.total.with_label_values(&[]).set(total);
+        }
+    }
+}
+
+/// Values the balances of one adapter, and holds its share of the portfolio.
+#[derive(Clone)]
+pub struct Valuation {
+    portfolio: Portfolio,
+    adapter: String,
+    group: Option<String>,
+}
+
+impl Valuation {
+    /// Adds a `BalanceValueUsd` observation for every balance of a priced
+    /// asset, replacing the ones from an earlier appraisal.
+    pub fn appraise(&self, observations: &mut Vec<Observation>) {
+        observations.retain(|observation| observation.family != Family::BalanceValueUsd);
+        let mut values = Vec::new();
+        for observation in observations.iter() {
+            let Measurement::Amount(amount) = observation.value else {
+                continue;
+            };
+            if observation.family != Family::Balance {
+                continue;
+            }
+            // Balance labels are address, asset and symbol.
+            let Some(price) = self.portfolio.price(&observation.labels[1]) else {
+                continue;
+            };
+            values.push(Observation {
+                family: Family::BalanceValueUsd,
+                labels: observation.labels.clone(),
+                value: Measurement::Float(amount.to_tokens() * price),
+                key: format!("{}_value_usd", observation.key),
+            });
+        }
+        observations.append(&mut values);
+    }
+}
+
+impl Share for Valuation {
+    /// Makes the sum of the `BalanceValueUsd` observations the adapter's share
+    /// of the portfolio, or drops its share when nothing was priced.
+    fn settle(&self, observations: &[Observation]) {
+        let values = observations
+            .iter()
+            .filter(|observation| observation.family == Family::BalanceValueUsd)
+            .filter_map(|observation| match observation.value {
+                Measurement::Float(value) if value.is_finite() => Some(value),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut state = self.portfolio.state.lock().unwrap();
+        if values.is_empty() {
+            state.values.remove(&self.adapter);
+        } else {
+            let value = values.iter().sum();
+            state
+                .values
+                .insert(self.adapter.clone(), (self.group.clone(), value));
+        }
+        self.portfolio.export(&state);
+    }
+
+    fn withdraw(&self) {
+        let mut state = self.portfolio.state.lock().unwrap();
+        state.values.remove(&self.adapter);
+        self.portfolio.export(&state);
+    }
+}
+
+/// The configured price sources, on the shared clients of their chains.
+pub struct PriceFeeds {
+    feeds: Vec<Feed>,
+    interval: Duration,
+}
+
+struct Feed {
+    ticker: String,
+    config: PriceConfig,
+    source: Source,
+    max_age: Duration,
+}
+
+enum Source {
+    Static(f64),
+    Pyth {
+        client: CosmosClient,
+        contract: String,
+        id: String,
+    },
+    Chainlink {
+        client: Arc<EvmProvider>,
+        aggregator: Address,
+    },
+    Cosmwasm {
+        client: CosmosClient,
+        contract: String,
+        query: String,
+        path: String,
+    },
+}
+
+impl PriceFeeds {
+    /// Prices are refreshed every `interval`.
+    pub fn new(
+        prices: &HashMap<String, PriceConfig>,
+        chains: &Chains,
+        interval: Duration,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut feeds = Vec::new();
+        for (ticker, config) in prices {
+            let endpoint = endpoint(&config.source).unwrap_or_default();
+            let source = match &config.source {
+                PriceSource::Static { peg } => Source::Static(*peg),
+                PriceSource::Pyth { contract, id, .. } => Source::Pyth {
+                    client: chains.cosmos(endpoint)?,
+                    contract: contract.clone(),
+                    id: id.clone(),
+                },
+                PriceSource::Chainlink { aggregator, .. } => Source::Chainlink {
+                    client: chains.evm(endpoint)?,
+                    aggregator: aggregator.parse()?,
+                },
+                PriceSource::Cosmwasm {
+                    contract,
+                    query,
+                    path,
+                    ..
+                } => Source::Cosmwasm {
+                    client: chains.cosmos(endpoint)?,
+                    contract: contract.clone(),
+                    query: query.clone(),
+                    path: path.clone(),
+                },
+            };
+            feeds.push(Feed {
+                ticker: ticker.clone(),
+                config: config.clone(),
+                source,
+                max_age: config.max_age.map_or(interval * 3, Duration::from_secs),
+            });
+        }
+        Ok(Self { feeds, interval })
+    }
+
+    /// Fetches every price once. A source that fails, or takes longer than
+    /// the interval, keeps its last price until it is `max_age` old.
+    pub async fn refresh(&self, portfolio: &Portfolio) {
+        let prices = join_all(self.feeds.iter().map(|feed| async {
+            match timeout(self.interval, feed.source.fetch()).await {
+                Ok(price) => price.map_err(|e| e.to_string()),
+                Err(_) => Err(format!("timed out after {}s", self.interval.as_secs())),
+            }
+        }))
+        .await;
+        for (feed, price) in self.feeds.iter().zip(prices) {
+            match price {
+                Ok(price) => portfolio.set_price(&feed.ticker, &feed.config, price),
+                Err(e) => {
+                    eprintln!("Error updating price {}: {}", feed.ticker, redact(&e));
+                    if portfolio.expire_price(&feed.ticker, feed.max_age) {
+                        eprintln!("Warning: price {} expired", feed.ticker);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Refreshes every price every interval, forever.
+    pub fn spawn(self, portfolio: Portfolio) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                self.refresh(&portfolio).await;
+            }
+        })
+    }
+}
+
+impl Source {
+    async fn fetch(&self) -> Result<f64, Box<dyn std::error::Error>> {
+        match self {
+            Source::Static(peg) => Ok(*peg),
+            Source::Pyth {
+                client,
+                contract,
+                id,
+            } => Ok(query_price(client, contract, id, None).await?.price),
+            Source::Chainlink { client, aggregator } => {
+                Ok(latest_round(Arc::clone(client), *aggregator, None)
+                    .await?
+                    .price)
+            }
+            Source::Cosmwasm {
+                client,
+                contract,
+                query,
+                path,
+            } => {
+                let answer = query_contract(client, contract, query, None).await?;
+                let price = match get_json_value_by_path(answer.into(), path)? {
+                    Some(Value::Number(price)) => price.as_f64(),
+                    Some(Value::String(price)) => price.parse().ok(),
+                    _ => None,
+                };
+                Ok(price.ok_or_else(|| format!("no number at {}", path))?)
+            }
+        }
+    }
+}
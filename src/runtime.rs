@@ -0,0 +1,628 @@
+use crate::adapter_factory::{
+    AdapterRegistry, TypedConfig, chain_label, create_adapter, log_warnings,
+};
+use crate::address_book::AddressBook;
+use crate::chains::Chains;
+use crate::config::{ChainConfig, Config, MetricConfig, mask_secrets, redact};
+use crate::export::MetricSet;
+use crate::instrumentation::Instrumentation;
+use crate::pricing::{self, Portfolio, PriceFeeds};
+use crate::scheduler::{BoxedAdapter, Job, Schedule, Scheduler};
+use crate::staleness::{Exposure, Share};
+use crate::status::Status;
+use prometheus::Registry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, interval};
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BUILD_RETRY_MIN: Duration = Duration::from_secs(5);
+const BUILD_RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// Owns the Prometheus registry and every running adapter task, and applies
+/// config changes by restarting only the adapters whose config or chain
+/// changed.
+pub struct Runtime {
+    registry: Arc<Registry>,
+    instrumentation: Instrumentation,
+    status: Status,
+    portfolio: Portfolio,
+    address_book: AddressBook,
+    prices: Option<JoinHandle<()>>,
+    scheduler: Scheduler,
+    adapter_types: AdapterRegistry,
+    chains: Chains,
+    config: Config,
+    adapters: HashMap<String, TypedConfig>,
+    running: HashMap<String, RunningAdapter>,
+    pending: HashMap<String, PendingAdapter>,
+}
+
+struct RunningAdapter {
+    config: MetricConfig,
+    chain: Option<ChainConfig>,
+    schedule: Schedule,
+    exposure: Exposure,
+    task: JoinHandle<()>,
+}
+
+/// An adapter whose build failed, usually because its rpc was unreachable,
+/// waiting to be built again.
+struct PendingAdapter {
+    config: MetricConfig,
+    chain: Option<ChainConfig>,
+    failures: u32,
+    retry_at: Instant,
+}
+
+impl Runtime {
+    /// Fails if any adapter config is invalid.
+    pub fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_adapter_types(config, AdapterRegistry::default())
+    }
+
+    /// Like `new`, with adapter types beyond the built-in ones.
+    pub fn with_adapter_types(
+        config: Config,
+        adapter_types: AdapterRegistry,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let adapters = adapter_types.validate(&config)?;
+        log_warnings(&adapters, |_| true);
+        let registry = Registry::new();
+        let instrumentation = Instrumentation::new(&registry)?;
+        let portfolio = Portfolio::new(&registry)?;
+        let address_book = AddressBook::new(&registry)?;
+        let status = Status::default();
+        address_book.set_entries(&config.addresses);
+        Ok(Self {
+            registry: Arc::new(registry),
+            scheduler: Scheduler::new(
+                config.concurrency as usize,
+                instrumentation.clone(),
+                status.clone(),
+            ),
+            instrumentation,
+            status,
+            portfolio,
+            address_book,
+            prices: None,
+            adapter_types,
+            chains: Chains::new(config.chains.clone()),
+            config,
+            adapters,
+            running: HashMap::new(),
+            pending: HashMap::new(),
+        })
+    }
+
+    pub fn registry(&self) -> Arc<Registry> {
+        Arc::clone(&self.registry)
+    }
+
+    /// The state of every adapter, for the JSON API.
+    pub fn status(&self) -> Status {
+        self.status.clone()
+    }
+
+    /// Fetches the prices, then builds every enabled adapter and starts its
+    /// refresh task.
+    pub async fn start(&mut self) {
+        let config = self.config.clone();
+        self.start_prices(&config).await;
+        let adapters = self.adapters.clone();
+        for (name, metric) in enabled(&config) {
+            self.start_adapter(name, metric, &config, &adapters[name])
+                .await;
+        }
+    }
+
+    /// Applies every config received on `reloads` and retries the adapters
+    /// whose build failed, until the channel is closed.
+    pub async fn run(&mut self, mut reloads: mpsc::Receiver<Config>) {
+        let mut retries = interval(BUILD_RETRY_MIN);
+        loop {
+            tokio::select! {
+                config = reloads.recv() => match config {
+                    Some(config) => self.reload(config).await,
+                    None => return,
+                },
+                _ = retries.tick() => self.retry_pending().await,
+            }
+        }
+    }
+
+    /// Builds every enabled adapter and refreshes each of them exactly once.
+    pub async fn run_once(&mut self) {
+        price_feeds(&self.config, &self.chains, &self.portfolio).await;
+        let config = self.config.clone();
+        let adapters = self.adapters.clone();
+        let mut jobs = Vec::new();
+        for (name, metric) in enabled(&config) {
+            let typed = &adapters[name];
+            if let Some(adapter) = self.build(name, metric, &config, typed).await {
+                let schedule = Schedule::new(&config, metric);
+                jobs.push(self.job(name, metric, &config, typed, schedule, adapter));
+            }
+        }
+        self.scheduler.run_once(jobs).await;
+    }
+
+    /// Builds the adapters whose backoff has passed since their build failed.
+    async fn retry_pending(&mut self) {
+        let now = Instant::now();
+        let due = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.retry_at <= now)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let config = self.config.clone();
+        let adapters = self.adapters.clone();
+        for name in due {
+            let Some(metric) = config.metrics.get(&name).filter(|metric| metric.enabled) else {
+                self.stop_adapter(&name);
+                continue;
+            };
+            println!("Retrying adapter {}", name);
+            if let Some(adapter) = self.build(&name, metric, &config, &adapters[&name]).await {
+                // A failed restart left the old adapter running.
+                self.stop_adapter(&name);
+                let schedule = Schedule::new(&config, metric);
+                self.spawn_adapter(&name, metric, &config, &adapters[&name], schedule, adapter);
+            }
+        }
+    }
+
+    /// Diffs `config` against the running one: removed adapters are stopped and
+    /// their gauges unregistered, new and changed ones are (re)built, and
+    /// unchanged ones keep running untouched. An invalid config is rejected
+    /// as a whole.
+    pub async fn reload(&mut self, config: Config) {
+        // Either config's secrets may show up in errors until one is kept.
+        mask_secrets(&[&self.config, &config]);
+        let adapters = match self.adapter_types.validate(&config) {
+            Ok(adapters) => adapters,
+            Err(e) => {
+                eprintln!(
+                    "Keeping the running config, the new one is {}",
+                    redact(&e.to_string())
+                );
+                mask_secrets(&[&self.config]);
+                return;
+            }
+        };
+        // Unchanged entries were warned about when they were loaded.
+        log_warnings(&adapters, |name| {
+            self.config.metrics.get(name) != config.metrics.get(name)
+        });
+
+        if config.concurrency != self.config.concurrency {
+            eprintln!(
+                "Changing concurrency requires a restart, keeping {}",
+                self.config.concurrency
+            );
+        }
+
+        let removed = self
+            .running
+            .keys()
+            .chain(self.pending.keys())
+            .filter(|name| !enabled(&config).any(|(n, _)| n == *name))
+            .cloned()
+            .collect::<HashSet<_>>();
+        for name in removed {
+            println!("Removing adapter {}", name);
+            self.stop_adapter(&name);
+        }
+
+        self.chains.reconfigure(config.chains.clone());
+        if config.prices != self.config.prices
+            || config.chains != self.config.chains
+            || config.interval != self.config.interval
+        {
+            self.start_prices(&config).await;
+        }
+        // Every adapter's series are named with the naming mode and labelled from
+        // the address book, so changing either restarts them all.
+        let relabel = config.legacy_metric_names != self.config.legacy_metric_names
+            || config.addresses != self.config.addresses;
+        self.address_book.set_entries(&config.addresses);
+        for (name, metric) in enabled(&config) {
+            let schedule = Schedule::new(&config, metric);
+            let chain = chain_definition(&config, &adapters[name]);
+            match self.running.get(name) {
+                Some(running)
+                    if !relabel
+                        && running.config == *metric
+                        && running.chain == chain
+                        && running.schedule == schedule =>
+                {
+                    // Back to the running config, drop a pending restart.
+                    self.pending.remove(name);
+                }
+                Some(_) => {
+                    println!("Restarting adapter {}", name);
+                    // Build the replacement first so a bad config keeps the old adapter alive.
+                    if let Some(adapter) = self.build(name, metric, &config, &adapters[name]).await
+                    {
+                        self.stop_adapter(name);
+                        self.spawn_adapter(
+                            name,
+                            metric,
+                            &config,
+                            &adapters[name],
+                            schedule,
+                            adapter,
+                        );
+                    }
+                }
+                None if self
+                    .pending
+                    .get(name)
+                    .is_some_and(|pending| pending.config == *metric && pending.chain == chain) =>
+                {
+                    // Unchanged, and waiting for its next retry.
+                }
+                None => {
+                    println!("Adding adapter {}", name);
+                    // Forget a pending build of an older config.
+                    self.stop_adapter(name);
+                    self.start_adapter(name, metric, &config, &adapters[name])
+                        .await;
+                }
+            }
+        }
+
+        let endpoints = enabled(&config)
+            .filter_map(|(name, _)| adapters[name].endpoint())
+            .chain(
+                config
+                    .prices
+                    .values()
+                    .filter_map(|price| pricing::endpoint(&price.source)),
+            );
+        self.chains.retain(endpoints);
+
+        mask_secrets(&[&config]);
+        self.config = config;
+        self.adapters = adapters;
+    }
+
+    /// Fetches every price once, so the first adapter updates are valued, and
+    /// then refreshes them in the background, replacing any running task.
+    async fn start_prices(&mut self, config: &Config) {
+        if let Some(task) = self.prices.take() {
+            task.abort();
+        }
+        self.portfolio.keep_prices(&config.prices);
+        if let Some(feeds) = price_feeds(config, &self.chains, &self.portfolio).await {
+            self.prices = Some(feeds.spawn(self.portfolio.clone()));
+        }
+    }
+
+    async fn start_adapter(
+        &mut self,
+        name: &str,
+        metric: &MetricConfig,
+        config: &Config,
+        typed: &TypedConfig,
+    ) {
+        if let Some(adapter) = self.build(name, metric, config, typed).await {
+            let schedule = Schedule::new(config, metric);
+            self.spawn_adapter(name, metric, config, typed, schedule, adapter);
+        }
+    }
+
+    /// Builds an adapter on the shared client of its chain. A failed build is
+    /// retried with backoff by `retry_pending`, and reported like a failed
+    /// update unless an older version of the adapter is still running.
+    async fn build(
+        &mut self,
+        name: &str,
+        metric: &MetricConfig,
+        config: &Config,
+        typed: &TypedConfig,
+    ) -> Option<BoxedAdapter> {
+        let started = Instant::now();
+        let error = match create_adapter(name.to_string(), typed, &self.chains).await {
+            Ok(adapter) => {
+                self.pending.remove(name);
+                return Some(adapter);
+            }
+            Err(e) => redact(&e.to_string()),
+        };
+        eprintln!("Error initializing adapter {}: {}", name, error);
+
+        if !self.running.contains_key(name) {
+            if !self.pending.contains_key(name) {
+                self.instrumentation.init(name, &metric.adapter);
+                self.status
+                    .init(name, &metric.adapter, chain_label(metric, typed));
+            }
+            self.instrumentation
+                .observe(name, &metric.adapter, started.elapsed(), false);
+            self.status
+                .record_failure(name, &format!("initializing: {}", error));
+        }
+
+        let failures = self.pending.get(name).map_or(0, |pending| pending.failures) + 1;
+        let backoff = BUILD_RETRY_MIN
+            .saturating_mul(1 << (failures - 1).min(16))
+            .min(BUILD_RETRY_MAX);
+        self.pending.insert(
+            name.to_string(),
+            PendingAdapter {
+                config: metric.clone(),
+                chain: chain_definition(config, typed),
+                failures,
+                retry_at: Instant::now() + backoff,
+            },
+        );
+        None
+    }
+
+    fn spawn_adapter(
+        &mut self,
+        name: &str,
+        metric: &MetricConfig,
+        config: &Config,
+        typed: &TypedConfig,
+        schedule: Schedule,
+        adapter: BoxedAdapter,
+    ) {
+        let job = self.job(name, metric, config, typed, schedule, adapter);
+        let exposure = job.exposure.clone();
+        let task = self.scheduler.spawn(job);
+        self.running.insert(
+            name.to_string(),
+            RunningAdapter {
+                config: metric.clone(),
+                chain: chain_definition(config, typed),
+                schedule,
+                exposure,
+                task,
+            },
+        );
+    }
+
+    fn stop_adapter(&mut self, name: &str) {
+        if let Some(running) = self.running.remove(name) {
+            running.task.abort();
+            running.exposure.withdraw();
+            self.instrumentation.remove(name, &running.config.adapter);
+            self.status.remove(name);
+        } else if let Some(pending) = self.pending.remove(name) {
+            self.instrumentation.remove(name, &pending.config.adapter);
+            self.status.remove(name);
+        }
+    }
+
+    fn job(
+        &self,
+        name: &str,
+        metric: &MetricConfig,
+        config: &Config,
+        typed: &TypedConfig,
+        schedule: Schedule,
+        adapter: BoxedAdapter,
+    ) -> Job {
+        let chain = chain_label(metric, typed);
+        let legacy = config.legacy_metric_names.then_some(typed.legacy_unit());
+        let metrics = MetricSet::new(name, chain, legacy, metric.unit);
+        let valuation = self.portfolio.valuation(name, metric.group.as_deref());
+        let holdings = self.address_book.holdings(name);
+        let shares: Vec<Box<dyn Share>> =
+            vec![Box::new(valuation.clone()), Box::new(holdings.clone())];
+        Job {
+            exposure: Exposure::new(self.registry(), metric.stale.clone(), metrics, shares),
+            valuation,
+            holdings,
+            adapter,
+            adapter_type: metric.adapter.clone(),
+            chain: chain.to_string(),
+            schedule,
+        }
+    }
+}
+
+/// Watches the config file for changes and, on Unix, listens for SIGHUP.
+/// Every successfully parsed config is sent on the returned channel; parse
+/// errors are logged and the running config is kept.
+pub fn watch_config(path: String) -> mpsc::Receiver<Config> {
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut last_content = std::fs::read_to_string(&path).ok();
+        let mut poll = interval(CONFIG_POLL_INTERVAL);
+        #[cfg(unix)]
+        let mut hangup =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+
+        loop {
+            #[cfg(unix)]
+            let forced = tokio::select! {
+                _ = poll.tick() => false,
+                Some(_) = async { hangup.as_mut()?.recv().await } => true,
+            };
+            #[cfg(not(unix))]
+            let forced = {
+                poll.tick().await;
+                false
+            };
+
+            let content = std::fs::read_to_string(&path).ok();
+            if !forced && content == last_content {
+                continue;
+            }
+            last_content = content;
+
+            println!("Reloading {}", path);
+            let config = match Config::from_file(&path) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("Error reloading {}: {}", path, redact(&e.to_string()));
+                    continue;
+                }
+            };
+            if tx.send(config).await.is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+fn enabled(config: &Config) -> impl Iterator<Item = (&String, &MetricConfig)> {
+    config.metrics.iter().filter(|(_, metric)| metric.enabled)
+}
+
+/// The `[chains.*]` entry an adapter uses, compared on reload to restart the
+/// adapters of a chain whose definition changed.
+fn chain_definition(config: &Config, typed: &TypedConfig) -> Option<ChainConfig> {
+    let chain = typed.endpoint()?.chain?;
+    config.chains.get(chain).cloned()
+}
+
+/// Builds the configured price feeds and fetches every price once.
+async fn price_feeds(
+    config: &Config,
+    chains: &Chains,
+    portfolio: &Portfolio,
+) -> Option<PriceFeeds> {
+    if config.prices.is_empty() {
+        return None;
+    }
+    let interval = Duration::from_secs(config.interval);
+    match PriceFeeds::new(&config.prices, chains, interval) {
+        Ok(feeds) => {
+            feeds.refresh(portfolio).await;
+            Some(feeds)
+        }
+        Err(e) => {
+            eprintln!("Error initializing prices: {}", redact(&e.to_string()));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::base::{AdapterConfig, MetricsAdapter, Observation};
+    use serde::Deserialize;
+    use tokio::task::Id;
+
+    /// An adapter that connects nowhere and observes nothing.
+    #[derive(Debug, Deserialize)]
+    struct Idle {
+        #[allow(dead_code)] // a change to it is a change to the metric config
+        value: u64,
+    }
+
+    struct IdleAdapter(String);
+
+    #[async_trait::async_trait]
+    impl MetricsAdapter for IdleAdapter {
+        fn get_name(&self) -> &str {
+            &self.0
+        }
+
+        async fn collect(&self) -> Result<Vec<Observation>, Box<dyn std::error::Error>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl AdapterConfig for Idle {
+        async fn build(
+            &self,
+            name: &str,
+            _chains: &Chains,
+        ) -> Result<Box<dyn MetricsAdapter + Send + Sync>, Box<dyn std::error::Error>> {
+            Ok(Box::new(IdleAdapter(name.to_string())))
+        }
+    }
+
+    /// A config with an `idle` adapter per `(name, value)`.
+    fn config(adapters: &[(&str, &str)], extra: &str) -> Config {
+        let mut text = format!("concurrency = 1\ninterval = 3600\n{}\n[metrics]\n", extra);
+        for (name, value) in adapters {
+            text.push_str(&format!(
+                "[metrics.{}]\nenabled = true\nadapter = \"idle\"\nconfig = {{ value = {} }}\n",
+                name, value
+            ));
+        }
+        toml::from_str(&text).unwrap()
+    }
+
+    async fn runtime(config: Config) -> Runtime {
+        let mut adapter_types = AdapterRegistry::empty();
+        adapter_types.register::<Idle>("idle");
+        let mut runtime = Runtime::with_adapter_types(config, adapter_types).unwrap();
+        runtime.start().await;
+        runtime
+    }
+
+    /// The task of every running adapter, by name.
+    fn tasks(runtime: &Runtime) -> HashMap<String, Id> {
+        runtime
+            .running
+            .iter()
+            .map(|(name, running)| (name.clone(), running.task.id()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reload_restarts_only_changed_adapters() {
+        let mut runtime = runtime(config(
+            &[("same", "1"), ("changed", "1"), ("removed", "1")],
+            "",
+        ))
+        .await;
+        let before = tasks(&runtime);
+        assert_eq!(before.len(), 3);
+
+        runtime
+            .reload(config(
+                &[("same", "1"), ("changed", "2"), ("added", "1")],
+                "",
+            ))
+            .await;
+        let after = tasks(&runtime);
+        let mut names = after.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["added", "changed", "same"]);
+        assert_eq!(after["same"], before["same"]);
+        assert_ne!(after["changed"], before["changed"]);
+        assert_eq!(runtime.config.metrics["changed"].config["value"], 2);
+    }
+
+    #[tokio::test]
+    async fn reload_restarts_every_adapter_when_naming_changes() {
+        let mut runtime = runtime(config(&[("a", "1"), ("b", "1")], "")).await;
+        let before = tasks(&runtime);
+
+        runtime
+            .reload(config(
+                &[("a", "1"), ("b", "1")],
+                "legacy_metric_names = true",
+            ))
+            .await;
+        let after = tasks(&runtime);
+        assert_ne!(after["a"], before["a"]);
+        assert_ne!(after["b"], before["b"]);
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_the_running_config_when_the_new_one_is_invalid() {
+        let mut runtime = runtime(config(&[("a", "1"), ("b", "1")], "")).await;
+        let before = tasks(&runtime);
+
+        let mut invalid = config(&[("a", "2"), ("b", "1")], "");
+        invalid.metrics.get_mut("b").unwrap().adapter = "unknown".to_string();
+        runtime.reload(invalid).await;
+        assert_eq!(tasks(&runtime), before);
+        assert_eq!(runtime.config.metrics["a"].config["value"], 1);
+    }
+}
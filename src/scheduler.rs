@@ -0,0 +1,403 @@
+use crate::adapters::base::MetricsAdapter;
+use crate::address_book::Holdings;
+use crate::config::{Config, MetricConfig, redact};
+use crate::instrumentation::Instrumentation;
+use crate::pricing::Valuation;
+use crate::staleness::Exposure;
+use crate::status::Status;
+use rand::Rng;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, MissedTickBehavior, sleep, timeout};
+
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Long enough for a slow RPC, short enough that a hung one frees its slot
+// in the gate long before an hourly adapter would be due again.
+const DEFAULT_UPDATE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type BoxedAdapter = Box<dyn MetricsAdapter + Send + Sync>;
+
+/// When and how urgently a single adapter is refreshed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub interval: Duration,
+    pub timeout: Duration, // of a single update, at most the interval
+    pub jitter: Duration,
+    pub priority: i32,
+}
+
+impl Schedule {
+    pub fn new(config: &Config, metric: &MetricConfig) -> Self {
+        let interval = Duration::from_secs(metric.interval.unwrap_or(config.interval));
+        let timeout = metric
+            .timeout
+            .or(config.timeout)
+            .map_or(DEFAULT_UPDATE_TIMEOUT, Duration::from_secs);
+        Self {
+            interval,
+            timeout: timeout.min(interval),
+            jitter: Duration::from_secs(metric.jitter),
+            priority: metric.priority,
+        }
+    }
+}
+
+/// An adapter together with everything the scheduler needs to run it.
+pub struct Job {
+    pub adapter: BoxedAdapter,
+    pub adapter_type: String,
+    pub chain: String,
+    pub schedule: Schedule,
+    pub exposure: Exposure,
+    pub valuation: Valuation,
+    pub holdings: Holdings,
+}
+
+/// Runs every adapter in its own task on its own cadence. Each task owns its
+/// adapter outright, so a slow RPC only delays that adapter; `concurrency`
+/// caps how many adapters may be refreshing at the same time, and waiting
+/// adapters are admitted by priority.
+pub struct Scheduler {
+    gate: Arc<PriorityGate>,
+    instrumentation: Instrumentation,
+    status: Status,
+}
+
+impl Scheduler {
+    pub fn new(concurrency: usize, instrumentation: Instrumentation, status: Status) -> Self {
+        Self {
+            gate: Arc::new(PriorityGate::new(concurrency)),
+            instrumentation,
+            status,
+        }
+    }
+
+    /// Spawns a task that takes ownership of the job's adapter and refreshes it forever.
+    pub fn spawn(&self, job: Job) -> JoinHandle<()> {
+        let gate = Arc::clone(&self.gate);
+        let instrumentation = self.instrumentation.clone();
+        let status = self.status.clone();
+        instrumentation.init(job.adapter.get_name(), &job.adapter_type);
+        status.init(job.adapter.get_name(), &job.adapter_type, &job.chain);
+        tokio::spawn(async move {
+            if !job.schedule.jitter.is_zero() {
+                let jitter_ms = job.schedule.jitter.as_millis() as u64;
+                let delay = Duration::from_millis(rand::thread_rng().gen_range(0..=jitter_ms));
+                sleep(delay).await;
+            }
+
+            let refresh = async {
+                let mut ticker = tokio::time::interval(job.schedule.interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    ticker.tick().await;
+                    let _permit = gate.acquire(job.schedule.priority).await;
+                    update(&job, &instrumentation, &status).await;
+                }
+            };
+            // Runs while an update waits for the gate or hangs. Values of
+            // balances whose price expired are withdrawn here too.
+            let expire = async {
+                let mut ticker = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+                loop {
+                    ticker.tick().await;
+                    job.exposure.expire();
+                    let revised = job
+                        .exposure
+                        .revise(|observations| job.valuation.appraise(observations));
+                    if let Err(e) = revised {
+                        eprintln!("Error revaluing {}: {}", job.adapter.get_name(), e);
+                    }
+                }
+            };
+            tokio::join!(refresh, expire);
+        })
+    }
+
+    /// Refreshes every adapter once, each in its own task, and waits for all of them.
+    pub async fn run_once(&self, jobs: Vec<Job>) {
+        let tasks = jobs
+            .into_iter()
+            .map(|job| {
+                let gate = Arc::clone(&self.gate);
+                let instrumentation = self.instrumentation.clone();
+                let status = self.status.clone();
+                instrumentation.init(job.adapter.get_name(), &job.adapter_type);
+                status.init(job.adapter.get_name(), &job.adapter_type, &job.chain);
+                tokio::spawn(async move {
+                    let _permit = gate.acquire(job.schedule.priority).await;
+                    update(&job, &instrumentation, &status).await
+                })
+            })
+            .collect::<Vec<_>>();
+        futures::future::join_all(tasks).await;
+    }
+}
+
+/// Refreshes the job's adapter once. An update that takes longer than the
+/// adapter's timeout fails, so a hung RPC cannot hold its slot in the gate.
+async fn update(job: &Job, instrumentation: &Instrumentation, status: &Status) {
+    let started = Instant::now();
+    let collected = timeout(job.schedule.timeout, job.adapter.collect())
+        .await
+        .unwrap_or_else(|_| {
+            Err(format!("timed out after {}s", job.schedule.timeout.as_secs()).into())
+        });
+    let result = match collected {
+        Ok(mut observations) => {
+            job.holdings.label(&mut observations);
+            job.valuation.appraise(&mut observations);
+            job.exposure
+                .record(&observations)
+                .map(|()| status.record_success(job.adapter.get_name(), &observations))
+        }
+        Err(e) => Err(e),
+    };
+    let success = match result {
+        Ok(()) => {
+            println!("Updated {}", job.adapter.get_name());
+            true
+        }
+        Err(e) => {
+            let error = redact(&e.to_string());
+            eprintln!("Error updating {}: {}", job.adapter.get_name(), error);
+            status.record_failure(job.adapter.get_name(), &error);
+            false
+        }
+    };
+
+    let name = job.adapter.get_name();
+    instrumentation.observe(name, &job.adapter_type, started.elapsed(), success);
+    if success {
+        if let Err(e) = job.exposure.record_success() {
+            eprintln!("Error registering metrics for {}: {}", name, e);
+        }
+    } else {
+        job.exposure.record_failure();
+    }
+}
+
+/// A counting semaphore that hands free slots to the highest-priority waiter,
+/// FIFO among equal priorities.
+struct PriorityGate {
+    state: Mutex<GateState>,
+}
+
+struct GateState {
+    available: usize,
+    next_seq: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+struct Waiter {
+    priority: i32,
+    seq: u64,
+    tx: oneshot::Sender<GatePermit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Returns its slot to the gate when dropped. A permit that was handed to a
+/// waiter which gave up before receiving it is dropped with the channel and
+/// released the same way.
+struct GatePermit {
+    gate: Option<Arc<PriorityGate>>,
+}
+
+impl Drop for GatePermit {
+    fn drop(&mut self) {
+        if let Some(gate) = self.gate.take() {
+            gate.release();
+        }
+    }
+}
+
+impl PriorityGate {
+    fn new(slots: usize) -> Self {
+        Self {
+            state: Mutex::new(GateState {
+                available: slots,
+                next_seq: 0,
+                waiters: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    async fn acquire(self: &Arc<Self>, priority: i32) -> GatePermit {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                return GatePermit {
+                    gate: Some(Arc::clone(self)),
+                };
+            }
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiters.push(Waiter { priority, seq, tx });
+            rx
+        };
+        // Senders are only dropped after a successful send, so this cannot fail.
+        rx.await.unwrap()
+    }
+
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(waiter) = state.waiters.pop() {
+            let permit = GatePermit {
+                gate: Some(Arc::clone(self)),
+            };
+            match waiter.tx.send(permit) {
+                Ok(()) => return,
+                // The waiter is gone; keep the slot without re-entering `release`.
+                Err(mut permit) => permit.gate = None,
+            }
+        }
+        state.available += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    /// The schedule of `[metrics.a]`, whose own settings are `metric`.
+    fn schedule(global: &str, metric: &str) -> Schedule {
+        let text = format!(
+            "concurrency = 1\n{}\n[metrics.a]\nenabled = true\nadapter = \"eth\"\nconfig = {{}}\n{}",
+            global, metric
+        );
+        let config: Config = toml::from_str(&text).unwrap();
+        Schedule::new(&config, &config.metrics["a"])
+    }
+
+    #[test]
+    fn schedule_bounds_updates_well_below_long_intervals() {
+        let schedule = schedule("interval = 3600", "");
+        assert_eq!(schedule.interval, Duration::from_secs(3600));
+        assert_eq!(schedule.timeout, DEFAULT_UPDATE_TIMEOUT);
+    }
+
+    #[test]
+    fn schedule_caps_the_timeout_at_the_interval() {
+        assert_eq!(
+            schedule("interval = 10", "").timeout,
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            schedule("interval = 60\ntimeout = 50", "interval = 20").timeout,
+            Duration::from_secs(20)
+        );
+    }
+
+    #[test]
+    fn schedule_timeout_is_configurable() {
+        assert_eq!(
+            schedule("interval = 600\ntimeout = 120", "").timeout,
+            Duration::from_secs(120)
+        );
+        assert_eq!(
+            schedule("interval = 600\ntimeout = 120", "timeout = 5").timeout,
+            Duration::from_secs(5)
+        );
+    }
+
+    /// Queues a waiter that records `id` once admitted and then frees its slot.
+    async fn wait(
+        gate: &Arc<PriorityGate>,
+        priority: i32,
+        id: u32,
+        admitted: &Arc<Mutex<Vec<u32>>>,
+    ) -> JoinHandle<()> {
+        let gate = Arc::clone(gate);
+        let admitted = Arc::clone(admitted);
+        let task = tokio::spawn(async move {
+            let _permit = gate.acquire(priority).await;
+            admitted.lock().unwrap().push(id);
+        });
+        // Let the task queue up before the next one does.
+        tokio::task::yield_now().await;
+        task
+    }
+
+    #[tokio::test]
+    async fn admits_immediately_while_slots_are_free() {
+        let gate = Arc::new(PriorityGate::new(2));
+        let first = gate.acquire(0).now_or_never();
+        let second = gate.acquire(0).now_or_never();
+        assert!(first.is_some() && second.is_some());
+        assert!(gate.acquire(0).now_or_never().is_none());
+        drop(first);
+        assert!(gate.acquire(0).now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn admits_by_priority_then_in_order() {
+        let gate = Arc::new(PriorityGate::new(1));
+        let admitted = Arc::new(Mutex::new(Vec::new()));
+        let held = gate.acquire(0).await;
+
+        let mut tasks = Vec::new();
+        for (id, priority) in [(1, 1), (2, 5), (3, 3), (4, 5), (5, 1)] {
+            tasks.push(wait(&gate, priority, id, &admitted).await);
+        }
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*admitted.lock().unwrap(), [2, 4, 3, 1, 5]);
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_waiter_keeps_no_slot() {
+        let gate = Arc::new(PriorityGate::new(1));
+        let admitted = Arc::new(Mutex::new(Vec::new()));
+        let held = gate.acquire(0).await;
+
+        let task = wait(&gate, 0, 1, &admitted).await;
+        task.abort();
+        let _ = task.await;
+        drop(held);
+        assert!(gate.acquire(0).now_or_never().is_some());
+        assert!(admitted.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_permit_handed_to_a_cancelled_waiter_is_released() {
+        let gate = Arc::new(PriorityGate::new(1));
+        let admitted = Arc::new(Mutex::new(Vec::new()));
+        let held = gate.acquire(0).await;
+
+        let task = wait(&gate, 0, 1, &admitted).await;
+        // Hands the slot to the waiting task, which is cancelled before it runs.
+        drop(held);
+        task.abort();
+        let _ = task.await;
+        assert!(gate.acquire(0).now_or_never().is_some());
+        assert!(admitted.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,457 @@
+use crate::adapters::base::{Observation, Value};
+use crate::config::{StalePolicy, StalenessConfig};
+use crate::export::MetricSet;
+use prometheus::Registry;
+use prometheus::core::Collector;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What an adapter adds to a total over all adapters, such as its share of
+/// the portfolio value. Settled by the adapter's `Exposure` on every
+/// successful update, and withdrawn when its values go stale or it stops.
+pub trait Share: Send + Sync {
+    /// Replaces the adapter's share with the one from `observations`.
+    fn settle(&self, observations: &[Observation]);
+    /// Removes the adapter's share until it is settled again.
+    fn withdraw(&self);
+}
+
+/// Decides which of an adapter's gauges are exported as its updates succeed
+/// and fail. Gauges are only registered after the first successful update, so
+/// an adapter that never worked does not export zeros, and they are set to NaN
+/// or unregistered once the adapter's staleness policy says they are stale.
+/// Its shares leave their totals at the same time, since a NaN would spoil
+/// the sum.
+///
+/// The handle is shared between the adapter's task and the runtime, which
+/// withdraws everything when the adapter is stopped.
+#[derive(Clone)]
+pub struct Exposure {
+    registry: Arc<Registry>,
+    policy: StalenessConfig,
+    state: Arc<Mutex<ExposureState>>,
+}
+
+struct ExposureState {
+    metrics: MetricSet,
+    gauges: Vec<(Value, bool)>, // (gauge, registered by us)
+    shares: Vec<Box<dyn Share>>,
+    last: Vec<Observation>, // of the last successful update
+    failures: u32,
+    last_success: Option<Instant>,
+    stale: bool,
+    withdrawn: bool,
+}
+
+impl Exposure {
+    pub fn new(
+        registry: Arc<Registry>,
+        policy: StalenessConfig,
+        metrics: MetricSet,
+        shares: Vec<Box<dyn Share>>,
+    ) -> Self {
+        Self {
+            registry,
+            policy,
+            state: Arc::new(Mutex::new(ExposureState {
+                metrics,
+                gauges: Vec::new(),
+                shares,
+                last: Vec::new(),
+                failures: 0,
+                last_success: None,
+                stale: false,
+                withdrawn: false,
+            })),
+        }
+    }
+
+    /// Sets the adapter's series and shares from a successful collection, which
+    /// makes them fresh. New series are exported by the following
+    /// `record_success`.
+    pub fn record(&self, observations: &[Observation]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        if state.withdrawn {
+            return Ok(());
+        }
+        self.set(&mut state, observations)?;
+        state.failures = 0;
+        state.last_success = Some(Instant::now());
+        state.stale = false;
+        Ok(())
+    }
+
+    /// Lets `revise` change the observations of the last update, and sets them
+    /// again if that changed which series there are, such as when a price
+    /// the balances were valued with expired. Values that went stale stay so.
+    pub fn revise(
+        &self,
+        revise: impl FnOnce(&mut Vec<Observation>),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        if state.withdrawn || state.stale {
+            return Ok(());
+        }
+        let mut observations = state.last.clone();
+        revise(&mut observations);
+        let keys = |observations: &[Observation]| {
+            observations
+                .iter()
+                .map(|observation| observation.key.clone())
+                .collect::<Vec<_>>()
+        };
+        if keys(&observations) == keys(&state.last) {
+            return Ok(());
+        }
+        self.set(&mut state, &observations)?;
+        Ok(self.export(&mut state)?)
+    }
+
+    fn set(
+        &self,
+        state: &mut ExposureState,
+        observations: &[Observation],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (created, retired) = state.metrics.record(observations)?;
+        state
+            .gauges
+            .extend(created.into_iter().map(|gauge| (gauge, false)));
+        for gauge in retired {
+            state.gauges.retain(|(other, registered)| {
+                let same = id(other) == id(&gauge);
+                if same && *registered {
+                    unregister(&self.registry, other);
+                }
+                !same
+            });
+        }
+        for share in &state.shares {
+            share.settle(observations);
+        }
+        state.last = observations.to_vec();
+        Ok(())
+    }
+
+    /// Exports every gauge, registering the ones that are not exported yet.
+    pub fn record_success(&self) -> prometheus::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.withdrawn {
+            return Ok(());
+        }
+        self.export(&mut state)
+    }
+
+    fn export(&self, state: &mut ExposureState) -> prometheus::Result<()> {
+        let mut result = Ok(());
+        for (gauge, registered) in state.gauges.iter_mut() {
+            if *registered {
+                continue;
+            }
+            match register(&self.registry, gauge) {
+                Ok(()) => *registered = true,
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.withdrawn {
+            return;
+        }
+        state.failures += 1;
+        let since_success = state.last_success.map(|at| at.elapsed());
+        if self.policy.is_stale(state.failures, since_success) {
+            self.go_stale(&mut state);
+        }
+    }
+
+    /// Applies the policy once the last success is older than `max_age`, so
+    /// values go stale even while an update hangs.
+    pub fn expire(&self) {
+        let mut state = self.state.lock().unwrap();
+        let Some(max_age) = self.policy.max_age else {
+            return;
+        };
+        let expired = state
+            .last_success
+            .is_some_and(|at| at.elapsed() >= Duration::from_secs(max_age));
+        if expired && !state.withdrawn {
+            self.go_stale(&mut state);
+        }
+    }
+
+    fn go_stale(&self, state: &mut ExposureState) {
+        if self.policy.policy != StalePolicy::Keep {
+            state.stale = true;
+            for share in &state.shares {
+                share.withdraw();
+            }
+        }
+        for (gauge, registered) in state.gauges.iter_mut() {
+            match self.policy.policy {
+                StalePolicy::Keep => {}
+                StalePolicy::Nan if gauge.set_nan() => {}
+                StalePolicy::Nan | StalePolicy::Drop => {
+                    if *registered {
+                        unregister(&self.registry, gauge);
+                        *registered = false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Unregisters everything for good; later updates are ignored.
+    pub fn withdraw(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.withdrawn = true;
+        for share in &state.shares {
+            share.withdraw();
+        }
+        for (gauge, registered) in state.gauges.iter_mut() {
+            if *registered {
+                unregister(&self.registry, gauge);
+                *registered = false;
+            }
+        }
+    }
+}
+
+fn register(registry: &Registry, gauge: &Value) -> prometheus::Result<()> {
+    match gauge {
+        Value::Int(g) => registry.register(Box::new(g.clone())),
+        Value::Float(g) => registry.register(Box::new(g.clone())),
+        Value::Vec(g) => registry.register(Box::new(g.clone())),
+    }
+}
+
+/// Identifies a collector by its name and constant labels.
+fn id(gauge: &Value) -> u64 {
+    match gauge {
+        Value::Int(g) => g.desc()[0].id,
+        Value::Float(g) => g.desc()[0].id,
+        Value::Vec(g) => g.desc()[0].id,
+    }
+}
+
+fn unregister(registry: &Registry, gauge: &Value) {
+    let _ = match gauge {
+        Value::Int(g) => registry.unregister(Box::new(g.clone())),
+        Value::Float(g) => registry.unregister(Box::new(g.clone())),
+        Value::Vec(g) => registry.unregister(Box::new(g.clone())),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::base::{Amount, ExportUnit, Family, Measurement};
+    use ethers::types::U256;
+
+    /// Logs what the exposure does with its share.
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+
+    impl Log {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl Share for Log {
+        fn settle(&self, observations: &[Observation]) {
+            let entry = format!("settle {}", observations.len());
+            self.0.lock().unwrap().push(entry);
+        }
+
+        fn withdraw(&self) {
+            self.0.lock().unwrap().push("withdraw".to_string());
+        }
+    }
+
+    fn observations() -> Vec<Observation> {
+        vec![
+            Observation::new(
+                Family::Balance,
+                &["0x1", "ETH", "ETH", "", "", ""],
+                Measurement::Amount(Amount::new(U256::from(150), 2)),
+                "balance_0x1".to_string(),
+            ),
+            Observation::new(
+                Family::SnapshotBlockHeight,
+                &[],
+                Measurement::Int(16),
+                "snapshot_block_height".to_string(),
+            ),
+        ]
+    }
+
+    /// An exposure with legacy names, whose block height is an integer gauge.
+    fn exposure(
+        policy: StalePolicy,
+        after_failures: Option<u32>,
+        max_age: Option<u64>,
+    ) -> (Exposure, Arc<Registry>, Log) {
+        let registry = Arc::new(Registry::new());
+        let log = Log::default();
+        let metrics = MetricSet::new("a", "ethereum", Some(ExportUnit::Raw), ExportUnit::Tokens);
+        let policy = StalenessConfig {
+            policy,
+            after_failures,
+            max_age,
+        };
+        let exposure = Exposure::new(
+            registry.clone(),
+            policy,
+            metrics,
+            vec![Box::new(log.clone())],
+        );
+        (exposure, registry, log)
+    }
+
+    fn update(exposure: &Exposure) {
+        exposure.record(&observations()).unwrap();
+        exposure.record_success().unwrap();
+    }
+
+    /// The names of the exported families, sorted.
+    fn names(registry: &Registry) -> Vec<String> {
+        let mut names = registry
+            .gather()
+            .iter()
+            .map(|family| family.get_name().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn value(registry: &Registry, name: &str) -> Option<f64> {
+        registry
+            .gather()
+            .iter()
+            .find(|family| family.get_name() == name)
+            .map(|family| family.get_metric()[0].get_gauge().get_value())
+    }
+
+    #[test]
+    fn record_exports_nothing_until_the_update_succeeds() {
+        let (exposure, registry, log) = exposure(StalePolicy::Drop, None, None);
+        exposure.record(&observations()).unwrap();
+        assert!(registry.gather().is_empty());
+        assert_eq!(log.take(), ["settle 2"]);
+
+        exposure.record_success().unwrap();
+        assert_eq!(
+            names(&registry),
+            [
+                "a_balance_0x1",
+                "a_snapshot_block_height",
+                "pex_balance",
+                "pex_snapshot_block_height"
+            ]
+        );
+        assert_eq!(value(&registry, "pex_balance"), Some(1.5));
+    }
+
+    #[test]
+    fn record_failure_keeps_values_under_the_keep_policy() {
+        let (exposure, registry, log) = exposure(StalePolicy::Keep, None, None);
+        update(&exposure);
+        log.take();
+        exposure.record_failure();
+        exposure.record_failure();
+        assert_eq!(names(&registry).len(), 4);
+        assert_eq!(value(&registry, "pex_balance"), Some(1.5));
+        assert!(log.take().is_empty());
+    }
+
+    #[test]
+    fn record_failure_sets_nan_after_the_allowed_failures() {
+        let (exposure, registry, log) = exposure(StalePolicy::Nan, Some(2), None);
+        update(&exposure);
+        log.take();
+
+        exposure.record_failure();
+        assert_eq!(value(&registry, "pex_balance"), Some(1.5));
+        assert!(log.take().is_empty());
+
+        exposure.record_failure();
+        assert!(value(&registry, "pex_balance").unwrap().is_nan());
+        assert!(value(&registry, "a_balance_0x1").unwrap().is_nan());
+        // The integer gauge cannot hold NaN and is dropped instead.
+        assert_eq!(value(&registry, "a_snapshot_block_height"), None);
+        assert_eq!(log.take(), ["withdraw"]);
+
+        update(&exposure);
+        assert_eq!(value(&registry, "pex_balance"), Some(1.5));
+        assert_eq!(value(&registry, "a_snapshot_block_height"), Some(16.0));
+        assert_eq!(log.take(), ["settle 2"]);
+    }
+
+    #[test]
+    fn record_failure_drops_values_under_the_drop_policy() {
+        let (exposure, registry, log) = exposure(StalePolicy::Drop, None, None);
+        update(&exposure);
+        log.take();
+
+        exposure.record_failure();
+        assert!(registry.gather().is_empty());
+        assert_eq!(log.take(), ["withdraw"]);
+
+        update(&exposure);
+        assert_eq!(names(&registry).len(), 4);
+        assert_eq!(log.take(), ["settle 2"]);
+    }
+
+    #[test]
+    fn record_success_resets_the_failure_count() {
+        let (exposure, registry, _) = exposure(StalePolicy::Drop, Some(2), None);
+        update(&exposure);
+        exposure.record_failure();
+        update(&exposure);
+        exposure.record_failure();
+        assert_eq!(value(&registry, "pex_balance"), Some(1.5));
+    }
+
+    #[test]
+    fn expire_leaves_values_younger_than_max_age() {
+        let (exposure, registry, log) = exposure(StalePolicy::Drop, None, Some(3600));
+        update(&exposure);
+        log.take();
+        exposure.expire();
+        assert_eq!(names(&registry).len(), 4);
+        assert!(log.take().is_empty());
+    }
+
+    #[test]
+    fn expire_applies_the_policy_after_max_age() {
+        // Values are never younger than a max_age of 0.
+        let (exposure, registry, log) = exposure(StalePolicy::Drop, Some(100), Some(0));
+        // Nothing expires before the first success.
+        exposure.expire();
+        assert!(log.take().is_empty());
+
+        update(&exposure);
+        log.take();
+        exposure.expire();
+        assert!(registry.gather().is_empty());
+        assert_eq!(log.take(), ["withdraw"]);
+    }
+
+    #[test]
+    fn withdraw_ignores_later_updates() {
+        let (exposure, registry, log) = exposure(StalePolicy::Keep, None, None);
+        update(&exposure);
+        log.take();
+        exposure.withdraw();
+        assert!(registry.gather().is_empty());
+        assert_eq!(log.take(), ["withdraw"]);
+
+        update(&exposure);
+        exposure.record_failure();
+        assert!(registry.gather().is_empty());
+        assert!(log.take().is_empty());
+    }
+}
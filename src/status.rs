@@ -0,0 +1,171 @@
+use crate::adapters::base::{Family, Measurement, Observation};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The last update of every running adapter, for the JSON API: whether it
+/// worked, the error if not, and the balances it read last. Balances are kept
+/// through failed updates; `updated_at` tells how old they are.
+#[derive(Clone, Default)]
+pub struct Status {
+    adapters: Arc<Mutex<HashMap<String, AdapterStatus>>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdapterStatus {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub adapter_type: String,
+    pub chain: String,
+    pub up: bool,                 // whether the last update succeeded
+    pub last_update: Option<i64>, // in seconds since the epoch, as are the times below
+    pub last_success: Option<i64>,
+    pub last_error: Option<String>,
+    pub failures: u32,             // consecutive failed updates
+    pub block_height: Option<u64>, // of the last successful update
+    #[serde(skip)]
+    balances: Vec<Balance>,
+}
+
+/// One balance as of its adapter's last successful update.
+#[derive(Debug, Clone, Serialize)]
+pub struct Balance {
+    pub adapter: String,
+    pub chain: String,
+    pub address: String,
+    pub asset: String,
+    pub symbol: String,
+    pub entity: String,
+    pub amount: String, // whole tokens, exact
+    pub decimals: Option<u8>,
+    pub value_usd: Option<f64>,
+    pub block_height: Option<u64>,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Portfolio {
+    pub balances: Vec<Balance>,
+}
+
+impl Status {
+    pub fn init(&self, name: &str, adapter_type: &str, chain: &str) {
+        let mut adapters = self.adapters.lock().unwrap();
+        adapters.insert(
+            name.to_string(),
+            AdapterStatus {
+                name: name.to_string(),
+                adapter_type: adapter_type.to_string(),
+                chain: chain.to_string(),
+                up: false,
+                last_update: None,
+                last_success: None,
+                last_error: None,
+                failures: 0,
+                block_height: None,
+                balances: Vec::new(),
+            },
+        );
+    }
+
+    /// Keeps the balances of a successful update, valued where a
+    /// `BalanceValueUsd` observation says so.
+    pub fn record_success(&self, name: &str, observations: &[Observation]) {
+        let now = chrono::Utc::now().timestamp();
+        let mut adapters = self.adapters.lock().unwrap();
+        let Some(status) = adapters.get_mut(name) else {
+            return;
+        };
+
+        let block_height = observations
+            .iter()
+            .find(|observation| observation.family == Family::SnapshotBlockHeight)
+            .and_then(|observation| match observation.value {
+                Measurement::Int(height) => u64::try_from(height).ok(),
+                _ => None,
+            });
+        let values = observations
+            .iter()
+            .filter(|observation| observation.family == Family::BalanceValueUsd)
+            .filter_map(|observation| match observation.value {
+                Measurement::Float(value) => Some((observation.key.as_str(), value)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        status.balances = observations
+            .iter()
+            .filter(|observation| observation.family == Family::Balance)
+            .map(|observation| {
+                let (amount, decimals) = match observation.value {
+                    Measurement::Int(value) => (value.to_string(), None),
+                    Measurement::Float(value) => (value.to_string(), None),
+                    Measurement::Amount(amount) => {
+                        (amount.to_decimal_string(), Some(amount.decimals))
+                    }
+                };
+                // Balance labels are address, asset, symbol, entity, purpose and tags.
+                let label = |i: usize| observation.labels.get(i).cloned().unwrap_or_default();
+                Balance {
+                    adapter: name.to_string(),
+                    chain: status.chain.clone(),
+                    address: label(0),
+                    asset: label(1),
+                    symbol: label(2),
+                    entity: label(3),
+                    amount,
+                    decimals,
+                    value_usd: values
+                        .get(format!("{}_value_usd", observation.key).as_str())
+                        .copied(),
+                    block_height,
+                    updated_at: now,
+                }
+            })
+            .collect();
+        status.up = true;
+        status.last_update = Some(now);
+        status.last_success = Some(now);
+        status.last_error = None;
+        status.failures = 0;
+        status.block_height = block_height;
+    }
+
+    pub fn record_failure(&self, name: &str, error: &str) {
+        let mut adapters = self.adapters.lock().unwrap();
+        if let Some(status) = adapters.get_mut(name) {
+            status.up = false;
+            status.last_update = Some(chrono::Utc::now().timestamp());
+            status.last_error = Some(error.to_string());
+            status.failures += 1;
+        }
+    }
+
+    /// Forgets an adapter that is no longer running.
+    pub fn remove(&self, name: &str) {
+        self.adapters.lock().unwrap().remove(name);
+    }
+
+    /// Every adapter, by name.
+    pub fn adapters(&self) -> Vec<AdapterStatus> {
+        let adapters = self.adapters.lock().unwrap();
+        let mut statuses = adapters.values().cloned().collect::<Vec<_>>();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses
+    }
+
+    pub fn adapter(&self, name: &str) -> Option<AdapterStatus> {
+        self.adapters.lock().unwrap().get(name).cloned()
+    }
+
+    /// The balances of every adapter, by adapter.
+    pub fn portfolio(&self) -> Portfolio {
+        Portfolio {
+            balances: self
+                .adapters()
+                .into_iter()
+                .flat_map(|status| status.balances)
+                .collect(),
+        }
+    }
+}